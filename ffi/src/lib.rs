@@ -0,0 +1,6 @@
+//! Static and shared C libraries of the [`farfalle::ffi`] bindings.
+//!
+//! `cargo build -p farfalle-ffi --release` writes `libfarfalle_ffi.a` and `libfarfalle_ffi.so`, or
+//! the platform's equivalents, to `target/release`. They are used with `include/farfalle.h`.
+
+pub use farfalle::ffi::*;
@@ -0,0 +1,266 @@
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#ifndef FARFALLE_H
+#define FARFALLE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Length of the tags of a [`FarfalleSane`] session.
+ */
+#define FARFALLE_SANE_TAG_LEN 16
+
+/**
+ * Length of the tags of a [`FarfalleSanse`] session.
+ */
+#define FARFALLE_SANSE_TAG_LEN 32
+
+/**
+ * Longest accepted key.
+ */
+#define FARFALLE_MAX_KEY_LEN 47
+
+/**
+ * Result of every function.
+ */
+typedef enum FarfalleStatus {
+  /**
+   * Success
+   */
+  FARFALLE_STATUS_OK = 0,
+  /**
+   * A required pointer was null
+   */
+  FARFALLE_STATUS_NULL_POINTER = 1,
+  /**
+   * A key or buffer has an unsupported length
+   */
+  FARFALLE_STATUS_INVALID_LENGTH = 2,
+  /**
+   * A tag did not match, the buffer is left untouched
+   */
+  FARFALLE_STATUS_AUTHENTICATION_FAILED = 3,
+  /**
+   * An internal error occurred
+   */
+  FARFALLE_STATUS_INTERNAL = 4,
+} FarfalleStatus;
+
+/**
+ * Xoofff deck function.
+ */
+typedef struct FarfalleDeck FarfalleDeck;
+
+/**
+ * Xoofff-SANE session.
+ */
+typedef struct FarfalleSane FarfalleSane;
+
+/**
+ * Xoofff-SANSE session.
+ */
+typedef struct FarfalleSanse FarfalleSanse;
+
+/**
+ * Xoofff wide block cipher.
+ */
+typedef struct FarfalleWbc FarfalleWbc;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create a deck keyed with `key`.
+ *
+ * # Safety
+ *
+ * `key` must be valid for reads of `key_len` bytes and `out` for writes of a pointer.
+ */
+FarfalleStatus farfalle_deck_new(const uint8_t *key, size_t key_len, FarfalleDeck **out);
+
+/**
+ * Append `data` to the input string of the deck.
+ *
+ * # Safety
+ *
+ * `deck` must be a live handle and `data` valid for reads of `data_len` bytes.
+ */
+FarfalleStatus farfalle_deck_update(FarfalleDeck *deck, const uint8_t *data, size_t data_len);
+
+/**
+ * End the current input string and write `out_len` bytes of output to `out`. The deck can
+ * then be updated further.
+ *
+ * # Safety
+ *
+ * `deck` must be a live handle and `out` valid for writes of `out_len` bytes.
+ */
+FarfalleStatus farfalle_deck_squeeze(FarfalleDeck *deck, uint8_t *out, size_t out_len);
+
+/**
+ * Release a deck. `deck` may be null.
+ *
+ * # Safety
+ *
+ * `deck` must be null or a live handle, which must not be used afterwards.
+ */
+void farfalle_deck_free(FarfalleDeck *deck);
+
+/**
+ * Start a Deck-SANE session with `key` and `iv`.
+ *
+ * # Safety
+ *
+ * `key` and `iv` must be valid for reads of their lengths and `out` for writes of a pointer.
+ */
+FarfalleStatus farfalle_sane_new(const uint8_t *key,
+                                 size_t key_len,
+                                 const uint8_t *iv,
+                                 size_t iv_len,
+                                 FarfalleSane **out);
+
+/**
+ * Encrypt the message in `buffer` in place and write its tag, [`FARFALLE_SANE_TAG_LEN`] bytes,
+ * to `tag`.
+ *
+ * # Safety
+ *
+ * `sane` must be a live handle, `ad` and `buffer` valid for their lengths and `tag` for writes
+ * of [`FARFALLE_SANE_TAG_LEN`] bytes.
+ */
+FarfalleStatus farfalle_sane_encrypt(FarfalleSane *sane,
+                                     const uint8_t *ad,
+                                     size_t ad_len,
+                                     uint8_t *buffer,
+                                     size_t buffer_len,
+                                     uint8_t *tag);
+
+/**
+ * Decrypt the message in `buffer` in place, checking its tag of [`FARFALLE_SANE_TAG_LEN`]
+ * bytes. On [`FarfalleStatus::AuthenticationFailed`] the session is unchanged.
+ *
+ * # Safety
+ *
+ * `sane` must be a live handle, `ad` and `buffer` valid for their lengths and `tag` for reads
+ * of [`FARFALLE_SANE_TAG_LEN`] bytes.
+ */
+FarfalleStatus farfalle_sane_decrypt(FarfalleSane *sane,
+                                     const uint8_t *ad,
+                                     size_t ad_len,
+                                     uint8_t *buffer,
+                                     size_t buffer_len,
+                                     const uint8_t *tag);
+
+/**
+ * Release a Deck-SANE session. `sane` may be null.
+ *
+ * # Safety
+ *
+ * `sane` must be null or a live handle, which must not be used afterwards.
+ */
+void farfalle_sane_free(FarfalleSane *sane);
+
+/**
+ * Start a Deck-SANSE session with `key`.
+ *
+ * # Safety
+ *
+ * `key` must be valid for reads of `key_len` bytes and `out` for writes of a pointer.
+ */
+FarfalleStatus farfalle_sanse_new(const uint8_t *key, size_t key_len, FarfalleSanse **out);
+
+/**
+ * Encrypt the message in `buffer` in place and write its tag, [`FARFALLE_SANSE_TAG_LEN`]
+ * bytes, to `tag`.
+ *
+ * # Safety
+ *
+ * `sanse` must be a live handle, `ad` and `buffer` valid for their lengths and `tag` for writes
+ * of [`FARFALLE_SANSE_TAG_LEN`] bytes.
+ */
+FarfalleStatus farfalle_sanse_encrypt(FarfalleSanse *sanse,
+                                      const uint8_t *ad,
+                                      size_t ad_len,
+                                      uint8_t *buffer,
+                                      size_t buffer_len,
+                                      uint8_t *tag);
+
+/**
+ * Decrypt the message in `buffer` in place, checking its tag of [`FARFALLE_SANSE_TAG_LEN`]
+ * bytes. On [`FarfalleStatus::AuthenticationFailed`] the session is unchanged.
+ *
+ * # Safety
+ *
+ * `sanse` must be a live handle, `ad` and `buffer` valid for their lengths and `tag` for reads
+ * of [`FARFALLE_SANSE_TAG_LEN`] bytes.
+ */
+FarfalleStatus farfalle_sanse_decrypt(FarfalleSanse *sanse,
+                                      const uint8_t *ad,
+                                      size_t ad_len,
+                                      uint8_t *buffer,
+                                      size_t buffer_len,
+                                      const uint8_t *tag);
+
+/**
+ * Release a Deck-SANSE session. `sanse` may be null.
+ *
+ * # Safety
+ *
+ * `sanse` must be null or a live handle, which must not be used afterwards.
+ */
+void farfalle_sanse_free(FarfalleSanse *sanse);
+
+/**
+ * Create a wide block cipher keyed with `key`.
+ *
+ * # Safety
+ *
+ * `key` must be valid for reads of `key_len` bytes and `out` for writes of a pointer.
+ */
+FarfalleStatus farfalle_wbc_new(const uint8_t *key, size_t key_len, FarfalleWbc **out);
+
+/**
+ * Encipher `buffer` in place under `tweak`. The handle can be reused.
+ *
+ * # Safety
+ *
+ * `wbc` must be a live handle and `tweak` and `buffer` valid for their lengths.
+ */
+FarfalleStatus farfalle_wbc_encrypt(const FarfalleWbc *wbc,
+                                    const uint8_t *tweak,
+                                    size_t tweak_len,
+                                    uint8_t *buffer,
+                                    size_t buffer_len);
+
+/**
+ * Decipher `buffer` in place under `tweak`. The handle can be reused.
+ *
+ * # Safety
+ *
+ * `wbc` must be a live handle and `tweak` and `buffer` valid for their lengths.
+ */
+FarfalleStatus farfalle_wbc_decrypt(const FarfalleWbc *wbc,
+                                    const uint8_t *tweak,
+                                    size_t tweak_len,
+                                    uint8_t *buffer,
+                                    size_t buffer_len);
+
+/**
+ * Release a wide block cipher. `wbc` may be null.
+ *
+ * # Safety
+ *
+ * `wbc` must be null or a live handle, which must not be used afterwards.
+ */
+void farfalle_wbc_free(FarfalleWbc *wbc);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FARFALLE_H */
@@ -0,0 +1,306 @@
+//! Command-line tool for the Farfalle modes of this crate.
+//!
+//! Keys are stored as hex in a key file, see `farfalle keygen`. Inputs and outputs default to
+//! stdin and stdout, `-` also selects them explicitly.
+//!
+//! `encrypt` writes the sealed file format of [`farfalle::file`] with a random IV. `decrypt` writes
+//! nothing until the whole input is verified: a file output is written to a temporary file next to
+//! it and only renamed into place afterwards, and output to stdout is buffered in memory.
+//!
+//! There is deliberately no `hash` subcommand. Farfalle is only secure under a secret key: with a
+//! public key the masks are known and the compression can be inverted, so an unkeyed Farfalle has
+//! easy collisions. Use `mac` with a secret key, or a dedicated hash function such as SHA-3.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use digest::typenum::Unsigned;
+use digest::{Update, XofReader};
+use subtle::ConstantTimeEq;
+
+use farfalle::core_api::CoreWrapper;
+use farfalle::deck::Deck;
+use farfalle::farfalle::{Farfalle, FarfalleCore};
+use farfalle::file::{FileAlgorithm, FileReader, FileWriter, IV_LEN, MAX_KEY_ID_LEN};
+use farfalle::instances::{KravatteCore, KravatteSane, XoofffCore, XoofffSane};
+use farfalle::params::MIN_TAG_SIZE;
+
+/// Length of the keys generated by `keygen`.
+const KEY_LEN: usize = 32;
+/// Default length of tags.
+const DEFAULT_LEN: usize = 32;
+
+#[derive(Parser)]
+#[command(version, about = "Encrypt and authenticate with Farfalle")]
+struct Cli {
+    /// The Farfalle instance to use
+    #[arg(long, value_enum, default_value_t = Instance::Xoofff)]
+    instance: Instance,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Instance {
+    Xoofff,
+    Kravatte,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a random key and write it as hex
+    Keygen {
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Encrypt and authenticate with Deck-SANE
+    Encrypt {
+        #[arg(short, long)]
+        key: PathBuf,
+        /// Key ID stored in the header
+        #[arg(long, default_value = "")]
+        key_id: String,
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Decrypt and verify the output of `encrypt`
+    Decrypt {
+        #[arg(short, long)]
+        key: PathBuf,
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compute a MAC of the input, written as hex
+    Mac {
+        #[arg(short, long)]
+        key: PathBuf,
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+        /// Length of the tag in bytes
+        #[arg(short, long, default_value_t = DEFAULT_LEN)]
+        length: usize,
+    },
+    /// Verify a MAC of the input, exiting with an error if it does not match
+    Verify {
+        #[arg(short, long)]
+        key: PathBuf,
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+        /// The tag as hex
+        #[arg(short, long)]
+        tag: String,
+    },
+    /// Write raw keystream for a key and nonce
+    Keystream {
+        #[arg(short, long)]
+        key: PathBuf,
+        /// The nonce as hex
+        #[arg(short, long, default_value = "")]
+        nonce: String,
+        /// Number of bytes to write
+        #[arg(short, long)]
+        length: u64,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.instance {
+        Instance::Xoofff => run::<XoofffCore, XoofffSane>(cli.command),
+        Instance::Kravatte => run::<KravatteCore, KravatteSane>(cli.command),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("farfalle: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run<C, S>(command: Command) -> io::Result<ExitCode>
+where
+    C: FarfalleCore,
+    S: FileAlgorithm<Core = Farfalle<C>>,
+{
+    match command {
+        Command::Keygen { output } => {
+            let mut key = [0; KEY_LEN];
+            getrandom::getrandom(&mut key).map_err(io::Error::from)?;
+            let mut output = open_output(output.as_deref())?;
+            writeln!(output, "{}", encode_hex(&key))?;
+            output.flush()?;
+        }
+        Command::Encrypt {
+            key,
+            key_id,
+            input,
+            output,
+        } => {
+            let key = read_key::<C>(&key)?;
+            if key_id.len() > MAX_KEY_ID_LEN {
+                return Err(invalid_data("key ID too long"));
+            }
+            let mut iv = [0; IV_LEN];
+            getrandom::getrandom(&mut iv).map_err(io::Error::from)?;
+
+            let mut input = open_input(input.as_deref())?;
+            let output = open_output(output.as_deref())?;
+            let mut writer = FileWriter::<_, S>::new(output, &key, key_id.as_bytes(), &iv)?;
+            io::copy(&mut input, &mut writer)?;
+            writer.finish()?;
+        }
+        Command::Decrypt { key, input, output } => {
+            let key = read_key::<C>(&key)?;
+            let input = open_input(input.as_deref())?;
+            let mut reader = FileReader::<_, S>::new(input, &key)?;
+            match output.as_deref() {
+                Some(path) if path != Path::new("-") => decrypt_to_file(&mut reader, path)?,
+                _ => {
+                    let mut plaintext = Vec::new();
+                    reader.read_to_end(&mut plaintext)?;
+                    let mut stdout = io::stdout().lock();
+                    stdout.write_all(&plaintext)?;
+                    stdout.flush()?;
+                }
+            }
+        }
+        Command::Mac { key, input, length } => {
+            let tag = mac::<C>(&read_key::<C>(&key)?, input.as_deref(), length)?;
+            println!("{}", encode_hex(&tag));
+        }
+        Command::Verify { key, input, tag } => {
+            let expected = decode_hex(&tag)?;
+            if expected.len() < MIN_TAG_SIZE {
+                return Err(invalid_data("tag too short"));
+            }
+            let actual = mac::<C>(&read_key::<C>(&key)?, input.as_deref(), expected.len())?;
+            if expected.ct_ne(&actual).into() {
+                eprintln!("farfalle: verification failed");
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Keystream {
+            key,
+            nonce,
+            length,
+            output,
+        } => {
+            let mut deck = CoreWrapper::<Farfalle<C>>::init(&read_key::<C>(&key)?);
+            deck.update(&decode_hex(&nonce)?);
+            let mut reader = deck.finalize_deck();
+
+            let mut output = open_output(output.as_deref())?;
+            let mut block = [0; 4096];
+            let mut remaining = length;
+            while remaining > 0 {
+                let n = remaining.min(block.len() as u64) as usize;
+                reader.read(&mut block[..n]);
+                output.write_all(&block[..n])?;
+                remaining -= n as u64;
+            }
+            output.flush()?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Write the plaintext to a temporary file next to `path`, and rename it to `path` only once the
+/// whole input is verified.
+fn decrypt_to_file(reader: &mut impl Read, path: &Path) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| invalid_data("invalid output path"))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(".partial");
+    let tmp = path.with_file_name(tmp_name);
+
+    let mut output = BufWriter::new(File::create_new(&tmp)?);
+    let result = io::copy(reader, &mut output)
+        .and_then(|_| output.into_inner().map_err(io::Error::from))
+        .and_then(|file| file.sync_all())
+        .and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn mac<C: FarfalleCore>(key: &[u8], input: Option<&Path>, length: usize) -> io::Result<Vec<u8>> {
+    let mut deck = CoreWrapper::<Farfalle<C>>::init(key);
+    absorb(&mut deck, input)?;
+    let mut tag = vec![0; length];
+    deck.finalize_deck().read(&mut tag);
+    Ok(tag)
+}
+
+/// Feed the whole input to `deck`.
+fn absorb(deck: &mut impl Update, input: Option<&Path>) -> io::Result<()> {
+    let mut input = open_input(input)?;
+    let mut block = [0; 8192];
+    loop {
+        match input.read(&mut block) {
+            Ok(0) => return Ok(()),
+            Ok(n) => deck.update(&block[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn open_input(path: Option<&Path>) -> io::Result<Box<dyn Read>> {
+    Ok(match path {
+        Some(path) if path != Path::new("-") => Box::new(BufReader::new(File::open(path)?)),
+        _ => Box::new(io::stdin().lock()),
+    })
+}
+
+fn open_output(path: Option<&Path>) -> io::Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) if path != Path::new("-") => Box::new(BufWriter::new(File::create(path)?)),
+        _ => Box::new(io::stdout().lock()),
+    })
+}
+
+/// Read a hex key file, checking the key fits in the state of the instance.
+fn read_key<C: FarfalleCore>(path: &Path) -> io::Result<Vec<u8>> {
+    let key = decode_hex(std::fs::read_to_string(path)?.trim())?;
+    if key.is_empty() || key.len() >= C::StateSize::USIZE {
+        return Err(invalid_data("invalid key length"));
+    }
+    Ok(key)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> io::Result<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return Err(invalid_data("odd number of hex digits"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| invalid_data("invalid hex digit"))
+        })
+        .collect()
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,79 @@
+//! Full-duplex channels.
+//!
+//! A [`DeckSane`] session only works when both ends process the same messages in lockstep.
+//! [`SessionChannel`] derives two independent sessions from one key and IV, one for each
+//! direction, so both ends can send at the same time.
+
+use digest::consts::U32;
+use hybrid_array::Array;
+
+use crate::core_api;
+use crate::deck::DeckCore;
+use crate::sane::{DeckSane, DeckSaneCore};
+use crate::sanse::apply_padded;
+
+/// Direction string of the initiator → responder session.
+const INITIATOR_TO_RESPONDER: &[u8] = &[0x01];
+/// Direction string of the responder → initiator session.
+const RESPONDER_TO_INITIATOR: &[u8] = &[0x02];
+
+/// Derive the key of one direction from the channel key.
+///
+/// The direction string carries a frame bit, so it is never a whole number of bytes like the IV
+/// that starts the history of a [`DeckSane`] session, and the derived keys are independent of
+/// any session under the channel key itself.
+fn direction_key<C: DeckCore>(key: &[u8], direction: &[u8]) -> Array<u8, U32> {
+    apply_padded::<_, 1>(&mut C::init(key), direction, 0b1).read_tag()
+}
+
+/// Which end of a [`SessionChannel`] this is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The end that started the connection
+    Initiator,
+    /// The end that accepted the connection
+    Responder,
+}
+
+/// A pair of [`DeckSane`] sessions, one for each direction of a full-duplex channel.
+///
+/// The session of each direction uses the shared IV under its own key, derived from the channel
+/// key and the direction, so the two keystreams and tag sequences are independent of each other
+/// and of any [`DeckSane`] session under the channel key.
+pub struct SessionChannel<D: DeckSaneCore> {
+    sender: DeckSane<D>,
+    receiver: DeckSane<D>,
+}
+
+impl<D: DeckSaneCore> SessionChannel<D> {
+    pub fn init(key: &[u8], iv: &[u8], role: Role) -> Self {
+        let mut i2r_key = direction_key::<D::Core>(key, INITIATOR_TO_RESPONDER);
+        let mut r2i_key = direction_key::<D::Core>(key, RESPONDER_TO_INITIATOR);
+        let i2r = DeckSane::init(&i2r_key, iv);
+        let r2i = DeckSane::init(&r2i_key, iv);
+        core_api::wipe(&mut i2r_key);
+        core_api::wipe(&mut r2i_key);
+
+        let (sender, receiver) = match role {
+            Role::Initiator => (i2r, r2i),
+            Role::Responder => (r2i, i2r),
+        };
+        Self { sender, receiver }
+    }
+
+    /// The session used to encrypt messages sent to the other end.
+    pub fn sender(&mut self) -> &mut DeckSane<D> {
+        &mut self.sender
+    }
+
+    /// The session used to decrypt messages received from the other end.
+    pub fn receiver(&mut self) -> &mut DeckSane<D> {
+        &mut self.receiver
+    }
+
+    /// Split the channel into its sending and receiving sessions, for example to use them
+    /// from separate threads.
+    pub fn split(self) -> (DeckSane<D>, DeckSane<D>) {
+        (self.sender, self.receiver)
+    }
+}
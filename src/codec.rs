@@ -0,0 +1,140 @@
+//! [`tokio_util::codec`] support for session-encrypted messages.
+//!
+//! [`SessionCodec`] seals each message as one message of a [`SessionAead`] session. Each frame
+//! on the wire is:
+//!
+//! | field      | size     | contents                                                   |
+//! |------------|----------|------------------------------------------------------------|
+//! | header     | 4        | message length as `u32` LE, top bit clear                  |
+//! | ciphertext | length   | the encrypted message                                      |
+//! | tag        | `S::Tag` | the tag of the message, with the header as associated data |
+//!
+//! This is the framing of [`io`](crate::io), without a last frame: messages are at most
+//! [`MAX_FRAME_SIZE`] bytes, and a header with the top bit set is rejected.
+//!
+//! Use one codec to encode and a second one, with the same session parameters, to decode. As in
+//! [`io`](crate::io), a decoder fails closed: after any error, every further call returns an
+//! error.
+
+use std::io;
+
+use bytes::{BufMut, BytesMut};
+use hybrid_array::{Array, typenum::Unsigned};
+use inout::InOutBuf;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::SessionAead;
+use crate::io::{
+    HEADER_LEN, MAX_FRAME_SIZE, authentication_failed, decode_header, encode_header,
+    frame_too_large, poisoned,
+};
+
+/// Default maximum message length.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024;
+
+/// Encodes or decodes session-sealed messages.
+pub struct SessionCodec<S: SessionAead> {
+    session: S,
+    max_frame_size: usize,
+    poisoned: bool,
+}
+
+impl<S: SessionAead> SessionCodec<S> {
+    pub fn new(session: S) -> Self {
+        Self::with_max_frame_size(session, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Messages longer than `max_frame_size` are rejected by both the encoder and the decoder.
+    ///
+    /// # Panics
+    ///
+    /// If `max_frame_size` is larger than [`MAX_FRAME_SIZE`].
+    pub fn with_max_frame_size(session: S, max_frame_size: usize) -> Self {
+        assert!(max_frame_size <= MAX_FRAME_SIZE);
+        Self {
+            session,
+            max_frame_size,
+            poisoned: false,
+        }
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+}
+
+impl<S: SessionAead> Encoder<&[u8]> for SessionCodec<S> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> io::Result<()> {
+        if item.len() > self.max_frame_size {
+            return Err(frame_too_large());
+        }
+        let header = encode_header(item.len(), false);
+
+        dst.reserve(HEADER_LEN + item.len() + S::Tag::USIZE);
+        dst.put_slice(&header);
+        let start = dst.len();
+        dst.put_slice(item);
+
+        let tag = self
+            .session
+            .encrypt_inout_detached(&header, InOutBuf::from(&mut dst[start..]));
+        dst.put_slice(&tag);
+        Ok(())
+    }
+}
+
+impl<S: SessionAead> Decoder for SessionCodec<S> {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let mut header = [0; HEADER_LEN];
+        header.copy_from_slice(&src[..HEADER_LEN]);
+        let len = match decode_header(header, self.max_frame_size) {
+            Ok((len, false)) => len,
+            Ok((_, true)) => {
+                self.poisoned = true;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "session frame marked as last",
+                ));
+            }
+            Err(e) => {
+                self.poisoned = true;
+                return Err(e);
+            }
+        };
+
+        let frame_len = HEADER_LEN + len + S::Tag::USIZE;
+        if src.len() < frame_len {
+            // wait for the rest of the frame
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        let mut frame = src.split_to(frame_len);
+        let _ = frame.split_to(HEADER_LEN);
+        let tag = frame.split_off(len);
+        let tag = Array::<u8, S::Tag>::try_from(&tag[..]).expect("tag length mismatch");
+
+        if self
+            .session
+            .decrypt_inout_detached(&header, InOutBuf::from(&mut frame[..]), &tag)
+            .is_err()
+        {
+            self.poisoned = true;
+            return Err(authentication_failed());
+        }
+
+        Ok(Some(frame))
+    }
+}
@@ -7,6 +7,8 @@ use digest::{
     core_api::{BufferKindUser, UpdateCore, XofReaderCore},
 };
 use inout::InOutBuf;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::{
     dec::{Dec, DecCore},
@@ -26,9 +28,31 @@ pub(crate) fn xor<'out, R: XofReader>(reader: &mut R, mut buffer: InOutBuf<'_, '
         reader.read(&mut block[..n]);
         last.xor_in2out(&block[..n]);
     }
+
+    wipe_block(&mut block);
+
     buffer.into_out()
 }
 
+/// Overwrite the buffer with zeros, in a way that will not be optimised away.
+#[inline]
+pub(crate) fn wipe(buffer: &mut [u8]) {
+    for b in buffer.iter_mut() {
+        // SAFETY: `b` is a valid, aligned and exclusive reference
+        unsafe { core::ptr::write_volatile(b, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Wipe a temporary block of key material or keystream, if the `zeroize` feature is enabled.
+#[inline(always)]
+pub(crate) fn wipe_block(block: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    block.zeroize();
+    #[cfg(not(feature = "zeroize"))]
+    let _ = block;
+}
+
 /// Wrapper around [`XofReaderCore`] implementations.
 ///
 /// It handles data buffering and implements the mid-level traits.
@@ -50,8 +74,34 @@ impl<T: XofReaderCore> XofReaderCoreWrapper<T> {
             buffer: ReadBuffer::default(),
         }
     }
+
+    /// Overwrite the buffered, unread keystream.
+    #[cfg(feature = "zeroize")]
+    fn wipe_buffer(&mut self) {
+        // SAFETY: `self.buffer` is a valid, aligned and exclusive reference
+        unsafe { core::ptr::write_volatile(&mut self.buffer, ReadBuffer::default()) };
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
 }
 
+#[cfg(feature = "zeroize")]
+impl<T: XofReaderCore> Drop for XofReaderCoreWrapper<T> {
+    fn drop(&mut self) {
+        self.wipe_buffer();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<T: XofReaderCore + Zeroize> Zeroize for XofReaderCoreWrapper<T> {
+    fn zeroize(&mut self) {
+        self.core.zeroize();
+        self.wipe_buffer();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<T: XofReaderCore + ZeroizeOnDrop> ZeroizeOnDrop for XofReaderCoreWrapper<T> {}
+
 impl<T> fmt::Debug for XofReaderCoreWrapper<T>
 where
     T: XofReaderCore + AlgorithmName,
@@ -84,6 +134,12 @@ pub struct CoreWrapper<T: BufferKindUser> {
     buffer: BlockBuffer<T::BlockSize, T::BufferKind>,
 }
 
+impl<T: BufferKindUser + AlgorithmName> AlgorithmName for CoreWrapper<T> {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::write_alg_name(f)
+    }
+}
+
 impl<D: UpdateCore + BufferKindUser> Update for CoreWrapper<D> {
     fn update(&mut self, input: &[u8]) {
         let Self { core, buffer } = self;
@@ -0,0 +1,250 @@
+//! Datagram mode.
+//!
+//! [`DeckSane`](crate::sane::DeckSane) and [`DeckSanse`](crate::sanse::DeckSanse) need every
+//! message to arrive, in order. For lossy transports such as UDP, [`DatagramSender`] and
+//! [`DatagramReceiver`] instead protect each packet on its own, under an explicit sequence number:
+//!
+//! | field      | size         | contents                             |
+//! |------------|--------------|--------------------------------------|
+//! | sequence   | 8            | sequence number as `u64` LE          |
+//! | ciphertext | variable     | the encrypted payload                |
+//! | tag        | `D::TagSize` | tag over sequence, AD and ciphertext |
+//!
+//! For each packet the keyed deck is cloned, so the key is only processed once per sender or
+//! receiver. The sequence number is applied to the clone and its output is the keystream of the
+//! payload; the associated data and the ciphertext are then applied to the same history and its
+//! output is the tag. Each sequence number must only be used once per key, which the sender
+//! guarantees by counting up from zero. Use a different key for each direction.
+//!
+//! A sender that is restarted with [`DatagramSender::init`] counts from zero again and reuses the
+//! keystream of every earlier packet. Either use a fresh key for each sender, or persist
+//! [`DatagramSender::next_seq`] before sending and restart with [`DatagramSender::resume`].
+//!
+//! The receiver accepts packets out of order, as long as they fall inside a sliding
+//! [`ReplayWindow`] of the most recent sequence numbers and have not been seen before.
+
+use digest::typenum::Unsigned;
+use hybrid_array::{Array, ArraySize};
+use inout::InOutBuf;
+use subtle::ConstantTimeEq;
+
+use crate::deck::DeckCore;
+use crate::sanse::{KeyStream, apply_padded};
+
+/// Core parameters used by [`DatagramSender`] and [`DatagramReceiver`]
+pub trait DeckDatagramCore {
+    type Core: DeckCore + Clone;
+    type TagSize: ArraySize;
+}
+
+/// Length of the sequence number at the start of each packet.
+pub const SEQUENCE_LEN: usize = 8;
+
+/// Number of sequence numbers below the highest one received that are still accepted.
+pub const WINDOW_SIZE: u64 = 128;
+
+/// Frames applied to the history of each packet.
+const FRAME_SEQUENCE: u8 = 0b00;
+const FRAME_AD: u8 = 0b01;
+const FRAME_CIPHERTEXT: u8 = 0b10;
+
+/// Apply the sequence number to a copy of the keyed deck, returning the history, ready for the
+/// associated data and ciphertext, and the keystream of the payload.
+fn begin_packet<C: DeckCore + Clone>(d: &C, seq: u64) -> (C, KeyStream<C::ReaderCore>) {
+    let mut d = d.clone();
+    let k = apply_padded::<_, 2>(&mut d, &seq.to_le_bytes(), FRAME_SEQUENCE);
+    (d, k)
+}
+
+/// Apply the associated data and ciphertext to the history of a packet, returning its tag.
+fn packet_tag<C: DeckCore, T: ArraySize>(mut d: C, ad: &[u8], ciphertext: &[u8]) -> Array<u8, T> {
+    apply_padded::<_, 2>(&mut d, ad, FRAME_AD);
+    apply_padded::<_, 2>(&mut d, ciphertext, FRAME_CIPHERTEXT).read_tag()
+}
+
+/// Encrypts packets under increasing sequence numbers.
+pub struct DatagramSender<D: DeckDatagramCore> {
+    d: D::Core,
+    next_seq: u64,
+}
+
+impl<D: DeckDatagramCore> DatagramSender<D> {
+    pub fn init(key: &[u8]) -> Self {
+        Self {
+            d: <D::Core as DeckCore>::init(key),
+            next_seq: 0,
+        }
+    }
+
+    /// Continue a sender created with the same key, starting at `next_seq`.
+    ///
+    /// `next_seq` must be at least the [`DatagramSender::next_seq`] of every earlier sender with
+    /// this key, otherwise sequence numbers and their keystreams are reused.
+    pub fn resume(key: &[u8], next_seq: u64) -> Self {
+        Self {
+            d: <D::Core as DeckCore>::init(key),
+            next_seq,
+        }
+    }
+
+    /// The sequence number of the next packet.
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Encrypt the payload in the buffer, returning its sequence number and tag.
+    ///
+    /// Fails once all sequence numbers have been used, as the key must then be replaced.
+    pub fn encrypt_inout_detached(
+        &mut self,
+        associated_data: &[u8],
+        buffer: InOutBuf<'_, '_, u8>,
+    ) -> aead::Result<(u64, Array<u8, D::TagSize>)> {
+        let seq = self.next_seq;
+        // `u64::MAX` is never used, so the receiver's window can always move past the last packet
+        if seq == u64::MAX {
+            return Err(aead::Error);
+        }
+        self.next_seq += 1;
+
+        let (d, k) = begin_packet(&self.d, seq);
+        let ciphertext = k.xor_in2out(buffer);
+        let tag = packet_tag(d, associated_data, ciphertext);
+        Ok((seq, tag))
+    }
+
+    /// Encrypt `payload` into `packet`, returning the length of the packet.
+    ///
+    /// `packet` must have room for the payload plus [`DatagramSender::overhead`] bytes.
+    pub fn seal(
+        &mut self,
+        associated_data: &[u8],
+        payload: &[u8],
+        packet: &mut [u8],
+    ) -> aead::Result<usize> {
+        let len = payload.len() + Self::overhead();
+        let packet = packet.get_mut(..len).ok_or(aead::Error)?;
+        let (header, rest) = packet.split_at_mut(SEQUENCE_LEN);
+        let (ciphertext, tag_dst) = rest.split_at_mut(payload.len());
+
+        let buffer = InOutBuf::new(payload, ciphertext).map_err(|_| aead::Error)?;
+        let (seq, tag) = self.encrypt_inout_detached(associated_data, buffer)?;
+        header.copy_from_slice(&seq.to_le_bytes());
+        tag_dst.copy_from_slice(&tag);
+        Ok(len)
+    }
+
+    /// The number of bytes a packet adds to its payload.
+    pub fn overhead() -> usize {
+        SEQUENCE_LEN + <D::TagSize as Unsigned>::USIZE
+    }
+}
+
+/// Sliding window of the sequence numbers received recently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplayWindow {
+    /// one more than the highest sequence number received, or 0 if none has been
+    next: u64,
+    /// bit `i` is set if `next - 1 - i` has been received
+    seen: u128,
+}
+
+impl ReplayWindow {
+    /// Whether `seq` may be accepted: it is newer than the window, or inside it and not yet seen.
+    /// `u64::MAX` is never accepted, as senders never use it.
+    pub fn check(&self, seq: u64) -> bool {
+        if seq >= self.next {
+            return seq != u64::MAX;
+        }
+        let age = self.next - 1 - seq;
+        age < WINDOW_SIZE && self.seen & (1 << age) == 0
+    }
+
+    /// Record `seq` as received, failing without changing the window if it does not pass
+    /// [`ReplayWindow::check`].
+    pub fn update(&mut self, seq: u64) -> aead::Result<()> {
+        if !self.check(seq) {
+            return Err(aead::Error);
+        }
+        if seq >= self.next {
+            let shift = seq - self.next + 1;
+            self.seen = if shift >= WINDOW_SIZE {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.next = seq + 1;
+        } else {
+            self.seen |= 1 << (self.next - 1 - seq);
+        }
+        Ok(())
+    }
+}
+
+/// Decrypts packets, rejecting replayed packets and packets older than the [`ReplayWindow`].
+pub struct DatagramReceiver<D: DeckDatagramCore> {
+    d: D::Core,
+    window: ReplayWindow,
+}
+
+impl<D: DeckDatagramCore> DatagramReceiver<D> {
+    pub fn init(key: &[u8]) -> Self {
+        Self {
+            d: <D::Core as DeckCore>::init(key),
+            window: ReplayWindow::default(),
+        }
+    }
+
+    pub fn window(&self) -> &ReplayWindow {
+        &self.window
+    }
+
+    /// Decrypt the payload of the packet with sequence number `seq` in the buffer.
+    ///
+    /// The window only moves once the tag has been verified, and the buffer is left untouched
+    /// on failure.
+    pub fn decrypt_inout_detached(
+        &mut self,
+        seq: u64,
+        associated_data: &[u8],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &Array<u8, D::TagSize>,
+    ) -> aead::Result<()> {
+        if !self.window.check(seq) {
+            return Err(aead::Error);
+        }
+
+        // the keystream only depends on the sequence number, so the tag can be checked first
+        let (d, k) = begin_packet(&self.d, seq);
+        let actual_tag = packet_tag::<_, D::TagSize>(d, associated_data, buffer.get_in());
+        if tag.ct_ne(&actual_tag).into() {
+            return Err(aead::Error);
+        }
+
+        self.window.update(seq)?;
+        k.xor_in2out(buffer);
+        Ok(())
+    }
+
+    /// Decrypt a packet in place, returning its sequence number and payload.
+    pub fn open<'a>(
+        &mut self,
+        associated_data: &[u8],
+        packet: &'a mut [u8],
+    ) -> aead::Result<(u64, &'a mut [u8])> {
+        let tag_size = <D::TagSize as Unsigned>::USIZE;
+        let payload_len = packet
+            .len()
+            .checked_sub(SEQUENCE_LEN + tag_size)
+            .ok_or(aead::Error)?;
+
+        let (header, rest) = packet.split_at_mut(SEQUENCE_LEN);
+        let (payload, tag) = rest.split_at_mut(payload_len);
+        let seq = u64::from_le_bytes(header.try_into().expect("header length"));
+        let tag = Array::<u8, D::TagSize>::try_from(&*tag).expect("tag length mismatch");
+
+        self.decrypt_inout_detached(seq, associated_data, InOutBuf::from(&mut *payload), &tag)?;
+        Ok((seq, payload))
+    }
+}
@@ -52,6 +52,25 @@ pub type OnlyZeros = WithTrailingZeros<UTerm>;
 /// pad10* as defined by the farfalle paper
 pub type Pad1X = WithTrailingZeros<D<B1, UTerm>>;
 
+/// Apply each associated data field to the history `d` as its own string with the frame bits `0b0e`,
+/// returning the output of the last one. No fields are applied as a single empty string.
+///
+/// Shared by Deck-SANE and Deck-SANSE, which frame associated data the same way.
+pub(crate) fn apply_ad<D: DeckCore>(d: &mut D, e: u8, ad: &[&[u8]]) -> D::ReaderCore {
+    let mut apply = |m: &[u8]| {
+        let mut buffer = Buffer::<D>::new(&[]);
+        buffer.digest_blocks(m, |b| d.update_blocks(b));
+        d.finalize_deck_prepadded::<2>(&mut buffer, e)
+    };
+    let Some((last, fields)) = ad.split_last() else {
+        return apply(&[]);
+    };
+    for field in fields {
+        apply(field);
+    }
+    apply(last)
+}
+
 /// Block-based core impl for Doubly-Extendable Cryptographic Keyed ([`Deck`]) functions.
 pub trait DeckCore: ExtendableOutputCore + UpdateCore {
     /// Retrieve XOF reader using remaining data stored in the block buffer and the lower `B` bits in delim
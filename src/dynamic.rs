@@ -0,0 +1,408 @@
+//! Runtime algorithm selection.
+//!
+//! [`Deck`] and [`SessionAead`] are generic over their sizes, so the algorithm has to be known at
+//! compile time. [`DynDeck`] and [`DynSessionAead`] are object-safe versions of these traits,
+//! implemented for every deck function and session, with the sizes reported at runtime instead.
+//!
+//! The registry maps the [`AlgorithmName`] of the standard [`instances`](crate::instances) to
+//! constructors of boxed trait objects, for example to pick an algorithm from a configuration
+//! file with [`find_session`]. Names are matched ignoring ASCII case.
+//!
+//! The methods that mirror [`Update`], [`Deck`] and [`SessionAead`] are prefixed with `dyn_`, so
+//! that bringing the object-safe traits into scope does not make calls on concrete types ambiguous.
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+
+use cipher::AlgorithmName;
+use crypto_common::{BlockSizeUser, InvalidLength};
+use digest::core_api::BufferKindUser;
+use digest::typenum::Unsigned;
+use digest::{Update, XofReader};
+use hybrid_array::Array;
+use inout::InOutBuf;
+
+use crate::SessionAead;
+use crate::core_api::CoreWrapper;
+use crate::deck::{Deck, DeckCore};
+#[cfg(feature = "kravatte")]
+use crate::instances::{Kravatte, KravatteSane, KravatteSanse};
+use crate::instances::{Xoofff, XoofffSane, XoofffSanse};
+use crate::params;
+use crate::sane::{DeckSane, DeckSaneCore};
+use crate::sanse::{DeckSanse, DeckSanseCore};
+
+/// Object-safe version of [`Deck`].
+pub trait DynDeck {
+    /// Append `data` to the current input string.
+    fn dyn_update(&mut self, data: &[u8]);
+
+    /// End the current input string and return a reader of the output. The deck can then be
+    /// updated further.
+    fn dyn_finalize_deck(&mut self) -> Box<dyn XofReader>;
+
+    /// End the current input string and fill `out` with the start of the output.
+    fn dyn_finalize_deck_into(&mut self, out: &mut [u8]) {
+        self.dyn_finalize_deck().read(out);
+    }
+}
+
+impl<D: Deck> DynDeck for D
+where
+    D::Reader: 'static,
+{
+    fn dyn_update(&mut self, data: &[u8]) {
+        Update::update(self, data);
+    }
+
+    fn dyn_finalize_deck(&mut self) -> Box<dyn XofReader> {
+        Box::new(self.finalize_deck())
+    }
+
+    fn dyn_finalize_deck_into(&mut self, out: &mut [u8]) {
+        self.finalize_deck().read(out);
+    }
+}
+
+/// Object-safe version of [`SessionAead`], with the tags passed as slices of
+/// [`tag_size`](DynSessionAead::tag_size) bytes.
+pub trait DynSessionAead {
+    /// The size of the tags in bytes.
+    fn tag_size(&self) -> usize;
+
+    /// See [`SessionAead::encrypt_vectored`].
+    ///
+    /// # Panics
+    ///
+    /// If `tag` is not [`tag_size`](Self::tag_size) bytes long.
+    fn dyn_encrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &mut [u8],
+    );
+
+    /// See [`SessionAead::decrypt_vectored`]. A tag of the wrong length is an invalid tag.
+    fn dyn_decrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &[u8],
+    ) -> aead::Result<()>;
+
+    /// See [`SessionAead::encrypt_inout_detached`].
+    ///
+    /// # Panics
+    ///
+    /// If `tag` is not [`tag_size`](Self::tag_size) bytes long.
+    fn dyn_encrypt_inout_detached(
+        &mut self,
+        associated_data: &[u8],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &mut [u8],
+    ) {
+        self.dyn_encrypt_vectored(&[associated_data], buffer, tag)
+    }
+
+    /// See [`SessionAead::decrypt_inout_detached`].
+    fn dyn_decrypt_inout_detached(
+        &mut self,
+        associated_data: &[u8],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &[u8],
+    ) -> aead::Result<()> {
+        self.dyn_decrypt_vectored(&[associated_data], buffer, tag)
+    }
+
+    /// See [`SessionAead::encrypt_in_place`].
+    fn dyn_encrypt_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut dyn aead::Buffer,
+    ) -> aead::Result<()> {
+        params::encrypt_in_place(buffer, self.tag_size(), |msg, tag| {
+            self.dyn_encrypt_inout_detached(associated_data, msg, tag)
+        })
+    }
+
+    /// See [`SessionAead::decrypt_in_place`].
+    fn dyn_decrypt_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut dyn aead::Buffer,
+    ) -> aead::Result<()> {
+        params::decrypt_in_place(buffer, self.tag_size(), |msg, tag| {
+            self.dyn_decrypt_inout_detached(associated_data, msg, tag)
+        })
+    }
+
+    /// See [`SessionAead::encrypt`].
+    fn dyn_encrypt(&mut self, associated_data: &[u8], plaintext: &[u8]) -> aead::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(plaintext.len() + self.tag_size());
+        out.extend_from_slice(plaintext);
+        self.dyn_encrypt_in_place(associated_data, &mut out)?;
+        Ok(out)
+    }
+
+    /// See [`SessionAead::decrypt`].
+    fn dyn_decrypt(&mut self, associated_data: &[u8], ciphertext: &[u8]) -> aead::Result<Vec<u8>> {
+        let mut out = ciphertext.to_vec();
+        self.dyn_decrypt_in_place(associated_data, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl<S: SessionAead> DynSessionAead for S {
+    fn tag_size(&self) -> usize {
+        S::Tag::USIZE
+    }
+
+    fn dyn_encrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &mut [u8],
+    ) {
+        assert_eq!(tag.len(), S::Tag::USIZE, "tag length mismatch");
+        tag.copy_from_slice(&SessionAead::encrypt_vectored(
+            self,
+            associated_data,
+            buffer,
+        ));
+    }
+
+    fn dyn_decrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &[u8],
+    ) -> aead::Result<()> {
+        let tag = Array::try_from(tag).map_err(|_| aead::Error)?;
+        SessionAead::decrypt_vectored(self, associated_data, buffer, &tag)
+    }
+}
+
+/// The [`AlgorithmName`] of a registered algorithm.
+#[derive(Clone, Copy)]
+struct Name(fn(&mut fmt::Formatter<'_>) -> fmt::Result);
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.0)(f)
+    }
+}
+
+impl Name {
+    fn matches(self, name: &str) -> bool {
+        self.to_string().eq_ignore_ascii_case(name)
+    }
+}
+
+/// A deck function in the registry.
+#[derive(Clone, Copy)]
+pub struct DeckAlgorithm {
+    name: Name,
+    state_size: usize,
+    init: fn(&[u8]) -> Box<dyn DynDeck + Send>,
+}
+
+impl DeckAlgorithm {
+    /// Register the deck function with the core `C`.
+    pub const fn new<C>() -> Self
+    where
+        C: DeckCore + BufferKindUser + AlgorithmName + Send + 'static,
+    {
+        Self {
+            name: Name(C::write_alg_name),
+            state_size: C::BlockSize::USIZE,
+            init: init_deck::<C>,
+        }
+    }
+
+    /// The name of the algorithm.
+    pub fn name(&self) -> impl fmt::Display {
+        self.name
+    }
+
+    /// The size of the permutation state in bytes.
+    pub fn state_size(&self) -> usize {
+        self.state_size
+    }
+
+    /// The longest accepted key in bytes, one less than the state size.
+    pub fn max_key_size(&self) -> usize {
+        self.state_size - 1
+    }
+
+    /// Create a deck keyed with `key`.
+    pub fn init(&self, key: &[u8]) -> Result<Box<dyn DynDeck + Send>, InvalidLength> {
+        if key.len() > self.max_key_size() {
+            return Err(InvalidLength);
+        }
+        Ok((self.init)(key))
+    }
+}
+
+impl fmt::Debug for DeckAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeckAlgorithm")
+            .field("name", &format_args!("{}", self.name))
+            .field("state_size", &self.state_size)
+            .finish()
+    }
+}
+
+fn init_deck<C>(key: &[u8]) -> Box<dyn DynDeck + Send>
+where
+    C: DeckCore + BufferKindUser + Send + 'static,
+{
+    Box::new(<CoreWrapper<C> as Deck>::init(key))
+}
+
+/// A session-supporting authenticated encryption scheme in the registry.
+#[derive(Clone, Copy)]
+pub struct SessionAlgorithm {
+    name: Name,
+    state_size: usize,
+    tag_size: usize,
+    takes_iv: bool,
+    init: fn(&[u8], &[u8]) -> Box<dyn DynSessionAead + Send>,
+}
+
+impl SessionAlgorithm {
+    /// Register the Deck-SANE mode `D`.
+    pub const fn sane<D>() -> Self
+    where
+        D: DeckSaneCore + AlgorithmName + 'static,
+        DeckSane<D>: Send,
+    {
+        Self {
+            name: Name(D::write_alg_name),
+            state_size: <D::Core as BlockSizeUser>::BlockSize::USIZE,
+            tag_size: D::TagSize::USIZE,
+            takes_iv: true,
+            init: init_sane::<D>,
+        }
+    }
+
+    /// Register the Deck-SANSE mode `D`.
+    pub const fn sanse<D>() -> Self
+    where
+        D: DeckSanseCore + AlgorithmName + 'static,
+        DeckSanse<D>: SessionAead + Send,
+    {
+        Self {
+            name: Name(D::write_alg_name),
+            state_size: <D::Core as BlockSizeUser>::BlockSize::USIZE,
+            tag_size: D::TagSize::USIZE,
+            takes_iv: false,
+            init: init_sanse::<D>,
+        }
+    }
+
+    /// The name of the algorithm.
+    pub fn name(&self) -> impl fmt::Display {
+        self.name
+    }
+
+    /// The size of the permutation state of the underlying deck function in bytes.
+    pub fn state_size(&self) -> usize {
+        self.state_size
+    }
+
+    /// The longest accepted key in bytes, one less than the state size.
+    pub fn max_key_size(&self) -> usize {
+        self.state_size - 1
+    }
+
+    /// The size of the tags in bytes.
+    pub fn tag_size(&self) -> usize {
+        self.tag_size
+    }
+
+    /// Whether sessions start with an IV. Deck-SANSE is nonce-misuse resistant and takes none.
+    pub fn takes_iv(&self) -> bool {
+        self.takes_iv
+    }
+
+    /// Start a session with `key` and `iv`.
+    ///
+    /// Returns [`InvalidLength`] if the key is too long, or if an IV is given to a scheme that
+    /// does not take one.
+    pub fn init(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Box<dyn DynSessionAead + Send>, InvalidLength> {
+        if key.len() > self.max_key_size() || (!self.takes_iv && !iv.is_empty()) {
+            return Err(InvalidLength);
+        }
+        Ok((self.init)(key, iv))
+    }
+}
+
+impl fmt::Debug for SessionAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionAlgorithm")
+            .field("name", &format_args!("{}", self.name))
+            .field("state_size", &self.state_size)
+            .field("tag_size", &self.tag_size)
+            .field("takes_iv", &self.takes_iv)
+            .finish()
+    }
+}
+
+fn init_sane<D>(key: &[u8], iv: &[u8]) -> Box<dyn DynSessionAead + Send>
+where
+    D: DeckSaneCore + 'static,
+    DeckSane<D>: Send,
+{
+    Box::new(DeckSane::<D>::init(key, iv))
+}
+
+fn init_sanse<D>(key: &[u8], _iv: &[u8]) -> Box<dyn DynSessionAead + Send>
+where
+    D: DeckSanseCore + 'static,
+    DeckSanse<D>: SessionAead + Send,
+{
+    Box::new(DeckSanse::<D>::init(key))
+}
+
+static DECKS: &[DeckAlgorithm] = &[
+    DeckAlgorithm::new::<Xoofff>(),
+    #[cfg(feature = "kravatte")]
+    DeckAlgorithm::new::<Kravatte>(),
+];
+
+static SESSIONS: &[SessionAlgorithm] = &[
+    SessionAlgorithm::sane::<XoofffSane>(),
+    SessionAlgorithm::sanse::<XoofffSanse>(),
+    #[cfg(feature = "kravatte")]
+    SessionAlgorithm::sane::<KravatteSane>(),
+    #[cfg(feature = "kravatte")]
+    SessionAlgorithm::sanse::<KravatteSanse>(),
+];
+
+/// All registered deck functions.
+pub fn decks() -> &'static [DeckAlgorithm] {
+    DECKS
+}
+
+/// All registered session-supporting authenticated encryption schemes.
+pub fn sessions() -> &'static [SessionAlgorithm] {
+    SESSIONS
+}
+
+/// Look up a deck function by name, ignoring ASCII case.
+pub fn find_deck(name: &str) -> Option<&'static DeckAlgorithm> {
+    DECKS.iter().find(|algorithm| algorithm.name.matches(name))
+}
+
+/// Look up a session-supporting authenticated encryption scheme by name, ignoring ASCII case.
+pub fn find_session(name: &str) -> Option<&'static SessionAlgorithm> {
+    SESSIONS
+        .iter()
+        .find(|algorithm| algorithm.name.matches(name))
+}
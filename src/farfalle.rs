@@ -1,12 +1,19 @@
+use core::fmt;
+
+use cipher::AlgorithmName;
 use digest::block_buffer::Eager;
 use digest::core_api::{BufferKindUser, ExtendableOutputCore, UpdateCore, XofReaderCore};
 use digest::crypto_common::{BlockSizeUser, BlockSizes};
 use digest::typenum::Unsigned;
 use hybrid_array::Array;
 use inout::InOut;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::Permutation;
+use crate::core_api;
 use crate::deck::DeckCore;
+use crate::snapshot::SerializeState;
 
 /// Definition of a farfalle construction.
 ///
@@ -55,6 +62,12 @@ impl<Core: FarfalleCore> Clone for Farfalle<Core> {
     }
 }
 
+impl<Core: FarfalleCore + AlgorithmName> AlgorithmName for Farfalle<Core> {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Core::write_alg_name(f)
+    }
+}
+
 impl<Core: FarfalleCore> BlockSizeUser for Farfalle<Core> {
     type BlockSize = Core::StateSize;
 }
@@ -70,9 +83,29 @@ impl<Core: FarfalleCore> Farfalle<Core> {
 
         Core::Pc::permute(&mut m);
         InOut::from(&mut self.x).xor_in2out(&m);
+
+        core_api::wipe_block(&mut m);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Core: FarfalleCore> Zeroize for Farfalle<Core> {
+    fn zeroize(&mut self) {
+        self.k.as_mut_slice().zeroize();
+        self.x.as_mut_slice().zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Core: FarfalleCore> Drop for Farfalle<Core> {
+    fn drop(&mut self) {
+        self.zeroize();
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl<Core: FarfalleCore> ZeroizeOnDrop for Farfalle<Core> {}
+
 impl<Core: FarfalleCore> UpdateCore for Farfalle<Core> {
     fn update_blocks(&mut self, blocks: &[crypto_common::Block<Self>]) {
         for m in blocks {
@@ -106,11 +139,49 @@ impl<Core: FarfalleCore> Clone for FarfalleXofCore<Core> {
     }
 }
 
+impl<Core: FarfalleCore> SerializeState for Farfalle<Core> {
+    const STATE_LEN: usize = 2 * <Core::StateSize as Unsigned>::USIZE;
+
+    fn serialize_state(&self, out: &mut [u8]) {
+        let (k, x) = out.split_at_mut(<Core::StateSize as Unsigned>::USIZE);
+        k.copy_from_slice(&self.k);
+        x.copy_from_slice(&self.x);
+    }
+
+    fn deserialize_state(state: &[u8]) -> Self {
+        let (k, x) = state.split_at(<Core::StateSize as Unsigned>::USIZE);
+        Self {
+            k: Array::try_from(k).expect("state must be STATE_LEN bytes"),
+            x: Array::try_from(x).expect("state must be STATE_LEN bytes"),
+        }
+    }
+}
+
+impl<Core: FarfalleCore> SerializeState for FarfalleXofCore<Core> {
+    const STATE_LEN: usize = 2 * <Core::StateSize as Unsigned>::USIZE;
+
+    fn serialize_state(&self, out: &mut [u8]) {
+        let (k, y) = out.split_at_mut(<Core::StateSize as Unsigned>::USIZE);
+        k.copy_from_slice(&self.k);
+        y.copy_from_slice(&self.y);
+    }
+
+    fn deserialize_state(state: &[u8]) -> Self {
+        let (k, y) = state.split_at(<Core::StateSize as Unsigned>::USIZE);
+        Self {
+            k: Array::try_from(k).expect("state must be STATE_LEN bytes"),
+            y: Array::try_from(y).expect("state must be STATE_LEN bytes"),
+        }
+    }
+}
+
 impl<Core: FarfalleCore> BlockSizeUser for FarfalleXofCore<Core> {
     type BlockSize = Core::StateSize;
 }
 
 impl<Core: FarfalleCore> XofReaderCore for FarfalleXofCore<Core> {
+    /// The returned block is keystream, and is wiped by the callers in this crate
+    /// when the `zeroize` feature is enabled.
     fn read_block(&mut self) -> crypto_common::Block<Self> {
         let mut b = self.y.clone();
         Core::Pe::permute(&mut b);
@@ -120,6 +191,24 @@ impl<Core: FarfalleCore> XofReaderCore for FarfalleXofCore<Core> {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl<Core: FarfalleCore> Zeroize for FarfalleXofCore<Core> {
+    fn zeroize(&mut self) {
+        self.k.as_mut_slice().zeroize();
+        self.y.as_mut_slice().zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Core: FarfalleCore> Drop for FarfalleXofCore<Core> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Core: FarfalleCore> ZeroizeOnDrop for FarfalleXofCore<Core> {}
+
 impl<Core: FarfalleCore> DeckCore for Farfalle<Core> {
     fn finalize_deck_prepadded<const B: u8>(
         &mut self,
@@ -129,9 +218,10 @@ impl<Core: FarfalleCore> DeckCore for Farfalle<Core> {
         assert!(B <= 7);
         debug_assert!(bits < (1 << B), "{bits} {B}");
 
-        // push a 1 bit
-        let delim = bits << 1 | 1;
-        let delim = delim << (7 - B);
+        // bits are read least significant first, as in XKCP: the first of the `B` frame bits,
+        // which callers pass as the most significant, goes in bit 0, followed by a 1 bit
+        let frame = (u16::from(bits.reverse_bits()) >> (8 - B)) as u8;
+        let delim = frame | 1 << B;
         buffer.digest_pad(delim, &[], |b| self.update_blocks(core::slice::from_ref(b)));
 
         Core::Rc::permute(&mut self.k);
@@ -148,7 +238,8 @@ impl<Core: FarfalleCore> DeckCore for Farfalle<Core> {
 
         let mut k = Array::<u8, Core::StateSize>::default();
         k[..key.len()].copy_from_slice(key);
-        k[key.len()] = 0x80;
+        // pad10*, least significant bit first
+        k[key.len()] = 0x01;
         Core::Pb::permute(&mut k);
 
         let x = Array::default();
@@ -0,0 +1,463 @@
+//! C bindings for the Xoofff instances.
+//!
+//! Every object is an opaque handle created by a `*_new` function and released by the matching
+//! `*_free` function. Functions return a [`FarfalleStatus`] instead of panicking, and leave their
+//! outputs untouched unless they return [`FarfalleStatus::Ok`]. Pointers may only be null when
+//! the matching length is zero.
+//!
+//! The C header `include/farfalle.h` is generated from this module with
+//! `cbindgen --config cbindgen.toml --output include/farfalle.h`. The static and shared libraries
+//! are built by the `farfalle-ffi` crate in `ffi/`, and `cargo xtask ffi` checks the header is up
+//! to date and runs the C tests against them.
+
+use core::ptr;
+use core::slice;
+
+use alloc::boxed::Box;
+use std::panic::{self, AssertUnwindSafe};
+
+use hybrid_array::{Array, typenum::Unsigned};
+use inout::InOutBuf;
+
+use crate::SessionAead;
+use crate::core_api::CoreWrapper;
+use crate::deck::Deck;
+use crate::farfalle::FarfalleCore;
+use crate::instances::{Xoofff, XoofffCore, XoofffSane, XoofffSanse, XoofffWbc};
+use crate::sane::{DeckSane, DeckSaneCore};
+use crate::sanse::{DeckSanse, DeckSanseCore};
+use crate::wbc::WideBlockCipher;
+
+/// Length of the tags of a [`FarfalleSane`] session.
+pub const FARFALLE_SANE_TAG_LEN: usize = 16;
+
+/// Length of the tags of a [`FarfalleSanse`] session.
+pub const FARFALLE_SANSE_TAG_LEN: usize = 32;
+
+/// Longest accepted key.
+pub const FARFALLE_MAX_KEY_LEN: usize = 47;
+
+// cbindgen only copies literals into the header, so the constants are checked against the
+// instances here
+const _: () = {
+    assert!(FARFALLE_SANE_TAG_LEN == <<XoofffSane as DeckSaneCore>::TagSize as Unsigned>::USIZE);
+    assert!(FARFALLE_SANSE_TAG_LEN == <<XoofffSanse as DeckSanseCore>::TagSize as Unsigned>::USIZE);
+    // the key is padded within a single state
+    assert!(
+        FARFALLE_MAX_KEY_LEN == <<XoofffCore as FarfalleCore>::StateSize as Unsigned>::USIZE - 1
+    );
+};
+
+/// Result of every function.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FarfalleStatus {
+    /// Success
+    Ok = 0,
+    /// A required pointer was null
+    NullPointer = 1,
+    /// A key or buffer has an unsupported length
+    InvalidLength = 2,
+    /// A tag did not match, the buffer is left untouched
+    AuthenticationFailed = 3,
+    /// An internal error occurred
+    Internal = 4,
+}
+
+/// Xoofff deck function.
+pub struct FarfalleDeck(CoreWrapper<Xoofff>);
+
+/// Xoofff-SANE session.
+pub struct FarfalleSane(DeckSane<XoofffSane>);
+
+/// Xoofff-SANSE session.
+pub struct FarfalleSanse(DeckSanse<XoofffSanse>);
+
+/// Xoofff wide block cipher.
+pub struct FarfalleWbc(WideBlockCipher<XoofffWbc>);
+
+/// Run `f`, turning a panic into [`FarfalleStatus::Internal`] instead of unwinding into C.
+fn guard(f: impl FnOnce() -> Result<(), FarfalleStatus>) -> FarfalleStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => FarfalleStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => FarfalleStatus::Internal,
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be null or valid for reads of `len` bytes.
+unsafe fn input<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], FarfalleStatus> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(FarfalleStatus::NullPointer)
+    } else {
+        Ok(unsafe { slice::from_raw_parts(ptr, len) })
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be null or valid for reads and writes of `len` bytes.
+unsafe fn output<'a>(ptr: *mut u8, len: usize) -> Result<&'a mut [u8], FarfalleStatus> {
+    if len == 0 {
+        Ok(&mut [])
+    } else if ptr.is_null() {
+        Err(FarfalleStatus::NullPointer)
+    } else {
+        Ok(unsafe { slice::from_raw_parts_mut(ptr, len) })
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be null or valid for reads and writes of `T`.
+unsafe fn object<'a, T>(ptr: *mut T) -> Result<&'a mut T, FarfalleStatus> {
+    unsafe { ptr.as_mut() }.ok_or(FarfalleStatus::NullPointer)
+}
+
+/// # Safety
+///
+/// `key` must be null or valid for reads of `key_len` bytes.
+unsafe fn key<'a>(key: *const u8, key_len: usize) -> Result<&'a [u8], FarfalleStatus> {
+    if key_len > FARFALLE_MAX_KEY_LEN {
+        return Err(FarfalleStatus::InvalidLength);
+    }
+    unsafe { input(key, key_len) }
+}
+
+/// Store a new handle in `out`.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes of a pointer.
+unsafe fn create<T>(
+    out: *mut *mut T,
+    f: impl FnOnce() -> Result<T, FarfalleStatus>,
+) -> FarfalleStatus {
+    if out.is_null() {
+        return FarfalleStatus::NullPointer;
+    }
+    guard(|| {
+        let handle = Box::into_raw(Box::new(f()?));
+        unsafe { ptr::write(out, handle) };
+        Ok(())
+    })
+}
+
+/// Create a deck keyed with `key`.
+///
+/// # Safety
+///
+/// `key` must be valid for reads of `key_len` bytes and `out` for writes of a pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_deck_new(
+    key: *const u8,
+    key_len: usize,
+    out: *mut *mut FarfalleDeck,
+) -> FarfalleStatus {
+    unsafe {
+        create(out, || {
+            Ok(FarfalleDeck(Deck::init(self::key(key, key_len)?)))
+        })
+    }
+}
+
+/// Append `data` to the input string of the deck.
+///
+/// # Safety
+///
+/// `deck` must be a live handle and `data` valid for reads of `data_len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_deck_update(
+    deck: *mut FarfalleDeck,
+    data: *const u8,
+    data_len: usize,
+) -> FarfalleStatus {
+    guard(|| {
+        let deck = unsafe { object(deck) }?;
+        digest::Update::update(&mut deck.0, unsafe { input(data, data_len) }?);
+        Ok(())
+    })
+}
+
+/// End the current input string and write `out_len` bytes of output to `out`. The deck can
+/// then be updated further.
+///
+/// # Safety
+///
+/// `deck` must be a live handle and `out` valid for writes of `out_len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_deck_squeeze(
+    deck: *mut FarfalleDeck,
+    out: *mut u8,
+    out_len: usize,
+) -> FarfalleStatus {
+    guard(|| {
+        let deck = unsafe { object(deck) }?;
+        let out = unsafe { output(out, out_len) }?;
+        digest::XofReader::read(&mut deck.0.finalize_deck(), out);
+        Ok(())
+    })
+}
+
+/// Release a deck. `deck` may be null.
+///
+/// # Safety
+///
+/// `deck` must be null or a live handle, which must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_deck_free(deck: *mut FarfalleDeck) {
+    if !deck.is_null() {
+        drop(unsafe { Box::from_raw(deck) });
+    }
+}
+
+/// Start a Deck-SANE session with `key` and `iv`.
+///
+/// # Safety
+///
+/// `key` and `iv` must be valid for reads of their lengths and `out` for writes of a pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_sane_new(
+    key: *const u8,
+    key_len: usize,
+    iv: *const u8,
+    iv_len: usize,
+    out: *mut *mut FarfalleSane,
+) -> FarfalleStatus {
+    unsafe {
+        create(out, || {
+            let key = self::key(key, key_len)?;
+            let iv = input(iv, iv_len)?;
+            Ok(FarfalleSane(DeckSane::init(key, iv)))
+        })
+    }
+}
+
+/// Encrypt the message in `buffer` in place and write its tag, [`FARFALLE_SANE_TAG_LEN`] bytes,
+/// to `tag`.
+///
+/// # Safety
+///
+/// `sane` must be a live handle, `ad` and `buffer` valid for their lengths and `tag` for writes
+/// of [`FARFALLE_SANE_TAG_LEN`] bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_sane_encrypt(
+    sane: *mut FarfalleSane,
+    ad: *const u8,
+    ad_len: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+    tag: *mut u8,
+) -> FarfalleStatus {
+    guard(|| {
+        let sane = unsafe { object(sane) }?;
+        let ad = unsafe { input(ad, ad_len) }?;
+        let buffer = unsafe { output(buffer, buffer_len) }?;
+        let tag = unsafe { output(tag, FARFALLE_SANE_TAG_LEN) }?;
+        tag.copy_from_slice(&sane.0.encrypt_inout_detached(ad, InOutBuf::from(buffer)));
+        Ok(())
+    })
+}
+
+/// Decrypt the message in `buffer` in place, checking its tag of [`FARFALLE_SANE_TAG_LEN`]
+/// bytes. On [`FarfalleStatus::AuthenticationFailed`] the session is unchanged.
+///
+/// # Safety
+///
+/// `sane` must be a live handle, `ad` and `buffer` valid for their lengths and `tag` for reads
+/// of [`FARFALLE_SANE_TAG_LEN`] bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_sane_decrypt(
+    sane: *mut FarfalleSane,
+    ad: *const u8,
+    ad_len: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+    tag: *const u8,
+) -> FarfalleStatus {
+    guard(|| {
+        let sane = unsafe { object(sane) }?;
+        let ad = unsafe { input(ad, ad_len) }?;
+        let buffer = unsafe { output(buffer, buffer_len) }?;
+        let tag = Array::try_from(unsafe { input(tag, FARFALLE_SANE_TAG_LEN) }?)
+            .map_err(|_| FarfalleStatus::InvalidLength)?;
+        sane.0
+            .decrypt_inout_detached(ad, InOutBuf::from(buffer), &tag)
+            .map_err(|_| FarfalleStatus::AuthenticationFailed)
+    })
+}
+
+/// Release a Deck-SANE session. `sane` may be null.
+///
+/// # Safety
+///
+/// `sane` must be null or a live handle, which must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_sane_free(sane: *mut FarfalleSane) {
+    if !sane.is_null() {
+        drop(unsafe { Box::from_raw(sane) });
+    }
+}
+
+/// Start a Deck-SANSE session with `key`.
+///
+/// # Safety
+///
+/// `key` must be valid for reads of `key_len` bytes and `out` for writes of a pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_sanse_new(
+    key: *const u8,
+    key_len: usize,
+    out: *mut *mut FarfalleSanse,
+) -> FarfalleStatus {
+    unsafe {
+        create(out, || {
+            Ok(FarfalleSanse(DeckSanse::init(self::key(key, key_len)?)))
+        })
+    }
+}
+
+/// Encrypt the message in `buffer` in place and write its tag, [`FARFALLE_SANSE_TAG_LEN`]
+/// bytes, to `tag`.
+///
+/// # Safety
+///
+/// `sanse` must be a live handle, `ad` and `buffer` valid for their lengths and `tag` for writes
+/// of [`FARFALLE_SANSE_TAG_LEN`] bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_sanse_encrypt(
+    sanse: *mut FarfalleSanse,
+    ad: *const u8,
+    ad_len: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+    tag: *mut u8,
+) -> FarfalleStatus {
+    guard(|| {
+        let sanse = unsafe { object(sanse) }?;
+        let ad = unsafe { input(ad, ad_len) }?;
+        let buffer = unsafe { output(buffer, buffer_len) }?;
+        let tag = unsafe { output(tag, FARFALLE_SANSE_TAG_LEN) }?;
+        tag.copy_from_slice(&sanse.0.encrypt_inout_detached(ad, InOutBuf::from(buffer)));
+        Ok(())
+    })
+}
+
+/// Decrypt the message in `buffer` in place, checking its tag of [`FARFALLE_SANSE_TAG_LEN`]
+/// bytes. On [`FarfalleStatus::AuthenticationFailed`] the session is unchanged.
+///
+/// # Safety
+///
+/// `sanse` must be a live handle, `ad` and `buffer` valid for their lengths and `tag` for reads
+/// of [`FARFALLE_SANSE_TAG_LEN`] bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_sanse_decrypt(
+    sanse: *mut FarfalleSanse,
+    ad: *const u8,
+    ad_len: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+    tag: *const u8,
+) -> FarfalleStatus {
+    guard(|| {
+        let sanse = unsafe { object(sanse) }?;
+        let ad = unsafe { input(ad, ad_len) }?;
+        let buffer = unsafe { output(buffer, buffer_len) }?;
+        let tag = Array::try_from(unsafe { input(tag, FARFALLE_SANSE_TAG_LEN) }?)
+            .map_err(|_| FarfalleStatus::InvalidLength)?;
+        sanse
+            .0
+            .decrypt_inout_detached(ad, InOutBuf::from(buffer), &tag)
+            .map_err(|_| FarfalleStatus::AuthenticationFailed)
+    })
+}
+
+/// Release a Deck-SANSE session. `sanse` may be null.
+///
+/// # Safety
+///
+/// `sanse` must be null or a live handle, which must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_sanse_free(sanse: *mut FarfalleSanse) {
+    if !sanse.is_null() {
+        drop(unsafe { Box::from_raw(sanse) });
+    }
+}
+
+/// Create a wide block cipher keyed with `key`.
+///
+/// # Safety
+///
+/// `key` must be valid for reads of `key_len` bytes and `out` for writes of a pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_wbc_new(
+    key: *const u8,
+    key_len: usize,
+    out: *mut *mut FarfalleWbc,
+) -> FarfalleStatus {
+    unsafe {
+        create(out, || {
+            Ok(FarfalleWbc(WideBlockCipher::init(self::key(key, key_len)?)))
+        })
+    }
+}
+
+/// Encipher `buffer` in place under `tweak`. The handle can be reused.
+///
+/// # Safety
+///
+/// `wbc` must be a live handle and `tweak` and `buffer` valid for their lengths.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_wbc_encrypt(
+    wbc: *const FarfalleWbc,
+    tweak: *const u8,
+    tweak_len: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+) -> FarfalleStatus {
+    guard(|| {
+        let wbc = unsafe { wbc.as_ref() }.ok_or(FarfalleStatus::NullPointer)?;
+        let tweak = unsafe { input(tweak, tweak_len) }?;
+        let buffer = unsafe { output(buffer, buffer_len) }?;
+        wbc.0.clone().encrypt_inout(tweak, InOutBuf::from(buffer));
+        Ok(())
+    })
+}
+
+/// Decipher `buffer` in place under `tweak`. The handle can be reused.
+///
+/// # Safety
+///
+/// `wbc` must be a live handle and `tweak` and `buffer` valid for their lengths.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_wbc_decrypt(
+    wbc: *const FarfalleWbc,
+    tweak: *const u8,
+    tweak_len: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+) -> FarfalleStatus {
+    guard(|| {
+        let wbc = unsafe { wbc.as_ref() }.ok_or(FarfalleStatus::NullPointer)?;
+        let tweak = unsafe { input(tweak, tweak_len) }?;
+        let buffer = unsafe { output(buffer, buffer_len) }?;
+        wbc.0.clone().decrypt_inout(tweak, InOutBuf::from(buffer));
+        Ok(())
+    })
+}
+
+/// Release a wide block cipher. `wbc` may be null.
+///
+/// # Safety
+///
+/// `wbc` must be null or a live handle, which must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn farfalle_wbc_free(wbc: *mut FarfalleWbc) {
+    if !wbc.is_null() {
+        drop(unsafe { Box::from_raw(wbc) });
+    }
+}
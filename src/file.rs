@@ -0,0 +1,417 @@
+//! Chunked file-encryption format on [`DeckSane`].
+//!
+//! A sealed file is a header followed by a sequence of chunks:
+//!
+//! | field      | size         | contents                                   |
+//! |------------|--------------|--------------------------------------------|
+//! | magic      | 4            | [`MAGIC`]                                  |
+//! | version    | 1            | [`VERSION`]                                |
+//! | algorithm  | 1            | [`FileAlgorithm::ALGORITHM_ID`]            |
+//! | chunk size | 4            | plaintext length of each chunk as `u32` LE |
+//! | key ID     | 1 + length   | length as `u8`, then an opaque key ID      |
+//! | IV         | [`IV_LEN`]   | unique per key                             |
+//! | chunks     | variable     | ciphertext followed by tag, per chunk      |
+//!
+//! The whole header is the IV of the session, so every tag authenticates it. Each chunk is sealed
+//! as one session message: every chunk but the last has exactly `chunk size` bytes of plaintext
+//! and [`CHUNK_AD`] as associated data, and the last chunk is shorter, possibly empty, and has
+//! [`FINAL_CHUNK_AD`]. The session chains every tag to the chunks before it, so reordered,
+//! spliced, dropped or truncated chunks are all detected. Any data after the last chunk is
+//! rejected.
+//!
+//! [`FileWriter`] and [`FileReader`] stream the format, [`seal_file`] and [`open_file`] work on
+//! whole files. Both fail closed like the adapters in [`io`](crate::io).
+
+use alloc::vec::Vec;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use hybrid_array::{Array, typenum::Unsigned};
+use inout::InOutBuf;
+
+use crate::SessionAead;
+#[cfg(feature = "kravatte")]
+use crate::instances::KravatteSane;
+use crate::instances::XoofffSane;
+use crate::sane::{DeckSane, DeckSaneCore};
+
+/// Magic bytes at the start of every sealed file.
+pub const MAGIC: [u8; 4] = *b"FRFL";
+
+/// Version of the format described in the [module documentation](self).
+pub const VERSION: u8 = 1;
+
+/// Length of the IV in the header.
+pub const IV_LEN: usize = 16;
+
+/// Largest key ID that fits in the header.
+pub const MAX_KEY_ID_LEN: usize = u8::MAX as usize;
+
+/// Default plaintext length of each chunk.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest chunk size accepted by [`FileReader`].
+pub const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Associated data of every chunk but the last.
+pub const CHUNK_AD: &[u8] = &[0x00];
+
+/// Associated data of the last chunk.
+pub const FINAL_CHUNK_AD: &[u8] = &[0x01];
+
+/// A [`DeckSaneCore`] with an identifier in the file format.
+pub trait FileAlgorithm: DeckSaneCore {
+    /// Identifier stored in the header.
+    const ALGORITHM_ID: u8;
+}
+
+impl FileAlgorithm for XoofffSane {
+    const ALGORITHM_ID: u8 = 1;
+}
+
+#[cfg(feature = "kravatte")]
+impl FileAlgorithm for KravatteSane {
+    const ALGORITHM_ID: u8 = 2;
+}
+
+/// The header of a sealed file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub algorithm: u8,
+    pub chunk_size: u32,
+    pub key_id: Vec<u8>,
+    pub iv: [u8; IV_LEN],
+}
+
+impl Header {
+    /// The encoded header.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(11 + self.key_id.len() + IV_LEN);
+        out.extend_from_slice(&MAGIC);
+        out.push(VERSION);
+        out.push(self.algorithm);
+        out.extend_from_slice(&self.chunk_size.to_le_bytes());
+        out.push(self.key_id.len() as u8);
+        out.extend_from_slice(&self.key_id);
+        out.extend_from_slice(&self.iv);
+        out
+    }
+
+    /// Read and parse a header, for example to look up the key by its ID before opening the
+    /// rest of the file with [`FileReader::with_header`].
+    ///
+    /// Only the structure is checked here: the header is authenticated with the first chunk.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut fixed = [0; 11];
+        reader.read_exact(&mut fixed)?;
+        if fixed[..4] != MAGIC {
+            return Err(invalid_data("not a sealed file"));
+        }
+        if fixed[4] != VERSION {
+            return Err(invalid_data("unsupported sealed file version"));
+        }
+        let algorithm = fixed[5];
+        let chunk_size = u32::from_le_bytes(fixed[6..10].try_into().unwrap());
+        if chunk_size == 0 || chunk_size as usize > MAX_CHUNK_SIZE {
+            return Err(invalid_data("invalid chunk size"));
+        }
+
+        let mut key_id = alloc::vec![0; fixed[10] as usize];
+        reader.read_exact(&mut key_id)?;
+        let mut iv = [0; IV_LEN];
+        reader.read_exact(&mut iv)?;
+
+        Ok(Self {
+            algorithm,
+            chunk_size,
+            key_id,
+            iv,
+        })
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("sealed file failed earlier")
+}
+
+/// Read until `buf` is full or the end of the input, returning the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Writes a sealed file to the inner writer.
+///
+/// [`FileWriter::finish`] must be called to write the last chunk, otherwise the file cannot be
+/// opened.
+pub struct FileWriter<W: Write, D: FileAlgorithm> {
+    inner: W,
+    session: DeckSane<D>,
+    /// plaintext of the chunk being filled
+    buffer: Vec<u8>,
+    chunk_size: usize,
+    poisoned: bool,
+}
+
+impl<W: Write, D: FileAlgorithm> FileWriter<W, D> {
+    /// Write the header. The IV must never be reused with the same key.
+    pub fn new(inner: W, key: &[u8], key_id: &[u8], iv: &[u8; IV_LEN]) -> io::Result<Self> {
+        Self::with_chunk_size(inner, key, key_id, iv, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// # Panics
+    ///
+    /// If `chunk_size` is zero or larger than [`MAX_CHUNK_SIZE`], or `key_id` is longer than
+    /// [`MAX_KEY_ID_LEN`].
+    pub fn with_chunk_size(
+        mut inner: W,
+        key: &[u8],
+        key_id: &[u8],
+        iv: &[u8; IV_LEN],
+        chunk_size: usize,
+    ) -> io::Result<Self> {
+        assert!((1..=MAX_CHUNK_SIZE).contains(&chunk_size));
+        assert!(key_id.len() <= MAX_KEY_ID_LEN);
+
+        let header = Header {
+            algorithm: D::ALGORITHM_ID,
+            chunk_size: chunk_size as u32,
+            key_id: key_id.to_vec(),
+            iv: *iv,
+        }
+        .encode();
+        inner.write_all(&header)?;
+
+        Ok(Self {
+            inner,
+            session: DeckSane::init(key, &header),
+            buffer: Vec::with_capacity(chunk_size),
+            chunk_size,
+            poisoned: false,
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Seal and write the buffered data as one chunk.
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        // the session moves forward here, so a failed write breaks the file
+        self.poisoned = true;
+
+        let ad = if last { FINAL_CHUNK_AD } else { CHUNK_AD };
+        let tag = self
+            .session
+            .encrypt_inout_detached(ad, InOutBuf::from(&mut self.buffer[..]));
+
+        self.inner.write_all(&self.buffer)?;
+        self.inner.write_all(&tag)?;
+        self.buffer.clear();
+
+        self.poisoned = false;
+        Ok(())
+    }
+
+    /// Write the last chunk, which is shorter than the chunk size and possibly empty, and flush,
+    /// returning the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write, D: FileAlgorithm> Write for FileWriter<W, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        let n = buf.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == self.chunk_size {
+            self.write_chunk(false)?;
+        }
+        Ok(n)
+    }
+
+    /// Flush the inner writer. Buffered data is only written once its chunk is complete.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads the plaintext of a sealed file from the inner reader.
+///
+/// Plaintext is only returned once the chunk containing it has been authenticated.
+pub struct FileReader<R: Read, D: FileAlgorithm> {
+    inner: R,
+    session: DeckSane<D>,
+    header: Header,
+    /// ciphertext and then plaintext of the current chunk
+    buffer: Vec<u8>,
+    /// position of the unread plaintext in `buffer`
+    pos: usize,
+    finished: bool,
+    poisoned: bool,
+}
+
+impl<R: Read, D: FileAlgorithm> FileReader<R, D> {
+    /// Read the header and prepare to open the file with `key`.
+    pub fn new(mut inner: R, key: &[u8]) -> io::Result<Self> {
+        let header = Header::read_from(&mut inner)?;
+        Self::with_header(inner, header, key)
+    }
+
+    /// Open the file with a header already read by [`Header::read_from`].
+    pub fn with_header(inner: R, header: Header, key: &[u8]) -> io::Result<Self> {
+        if header.algorithm != D::ALGORITHM_ID {
+            return Err(invalid_data("sealed file uses a different algorithm"));
+        }
+        let session = DeckSane::init(key, &header.encode());
+        Ok(Self {
+            inner,
+            session,
+            header,
+            buffer: Vec::new(),
+            pos: 0,
+            finished: false,
+            poisoned: false,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Whether the last chunk has been read.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Read, authenticate and decrypt the next chunk into the buffer.
+    fn read_chunk(&mut self) -> io::Result<()> {
+        // cleared once the chunk has been authenticated
+        self.poisoned = true;
+        let tag_size = D::TagSize::USIZE;
+        let chunk_size = self.header.chunk_size as usize;
+
+        self.buffer.resize(chunk_size + tag_size, 0);
+        self.pos = 0;
+        let n = read_full(&mut self.inner, &mut self.buffer)?;
+        let Some(len) = n.checked_sub(tag_size) else {
+            self.buffer.clear();
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        // only the last chunk is shorter than the chunk size
+        let last = len < chunk_size;
+        let ad = if last { FINAL_CHUNK_AD } else { CHUNK_AD };
+
+        let (ciphertext, tag) = self.buffer[..n].split_at_mut(len);
+        let tag = Array::<u8, D::TagSize>::try_from(&*tag).expect("tag length mismatch");
+        if self
+            .session
+            .decrypt_inout_detached(ad, InOutBuf::from(ciphertext), &tag)
+            .is_err()
+        {
+            self.buffer.clear();
+            return Err(invalid_data("sealed file chunk failed authentication"));
+        }
+        // the input must end right after the last chunk
+        if last && read_full(&mut self.inner, &mut [0])? != 0 {
+            self.buffer.clear();
+            return Err(invalid_data("trailing data after the last chunk"));
+        }
+
+        self.buffer.truncate(len);
+        self.finished = last;
+        self.poisoned = false;
+        Ok(())
+    }
+}
+
+impl<R: Read, D: FileAlgorithm> Read for FileReader<R, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pos == self.buffer.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.read_chunk()?;
+        }
+
+        let n = buf.len().min(self.buffer.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Seal the file at `src` into a new file at `dst`.
+pub fn seal_file<D: FileAlgorithm>(
+    key: &[u8],
+    key_id: &[u8],
+    iv: &[u8; IV_LEN],
+    src: &Path,
+    dst: &Path,
+) -> io::Result<()> {
+    let mut input = BufReader::new(File::open(src)?);
+    let output = BufWriter::new(File::create(dst)?);
+    let mut writer = FileWriter::<_, D>::new(output, key, key_id, iv)?;
+    io::copy(&mut input, &mut writer)?;
+    writer.finish()?.into_inner()?.sync_all()
+}
+
+/// Open the sealed file at `src` into a new file at `dst`.
+///
+/// The plaintext is written to a temporary file next to `dst`, which is only renamed to `dst`
+/// once the whole file is authenticated. On failure it is removed, so unauthenticated or
+/// incomplete plaintext is never left behind.
+pub fn open_file<D: FileAlgorithm>(key: &[u8], src: &Path, dst: &Path) -> io::Result<()> {
+    let input = BufReader::new(File::open(src)?);
+    let mut reader = FileReader::<_, D>::new(input, key)?;
+
+    let name = dst
+        .file_name()
+        .ok_or_else(|| invalid_data("invalid output path"))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(".partial");
+    let tmp = dst.with_file_name(tmp_name);
+
+    let mut output = BufWriter::new(File::create_new(&tmp)?);
+    let result = io::copy(&mut reader, &mut output)
+        .and_then(|_| output.into_inner().map_err(io::Error::from))
+        .and_then(|file| file.sync_all())
+        .and_then(|()| fs::rename(&tmp, dst));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
@@ -0,0 +1,270 @@
+//! Format-preserving encryption over small domains.
+//!
+//! [`WideBlockCipher`](crate::wbc::WideBlockCipher) only permutes byte strings, so it cannot
+//! map a 16 digit card number onto another 16 digit card number, or a value in `0..=max` onto
+//! another value in `0..=max`. [`DeckFpe`] provides two small-domain permutations using the
+//! deck function as the round function PRF:
+//!
+//! * an FF1-style balanced Feistel network over strings of numerals in some radix,
+//! * a Feistel network over bit strings with cycle-walking, for integers in `0..=max`.
+
+use core::fmt;
+
+use digest::consts::U16;
+
+use crate::deck::DeckCore;
+use crate::sanse::apply_padded;
+
+/// Number of Feistel rounds, as in FF1.
+const ROUNDS: u8 = 10;
+
+/// Minimum domain size accepted for numeral strings, as recommended in NIST SP 800-38G Rev. 1.
+pub const MIN_DOMAIN: u64 = 1_000_000;
+
+/// Maximum supported radix for numeral strings.
+pub const MAX_RADIX: u32 = 1 << 16;
+
+const MODE_NUMERALS: u8 = 0;
+const MODE_INTEGER: u8 = 1;
+
+/// Error returned when the input is not a valid member of the requested domain,
+/// or the domain itself is not supported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidDomain;
+
+impl fmt::Display for InvalidDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid format-preserving encryption domain")
+    }
+}
+
+impl core::error::Error for InvalidDomain {}
+
+/// Format-preserving encryption built on a [`Deck`](DeckCore) function.
+pub struct DeckFpe<D: DeckCore + Clone> {
+    d: D,
+}
+
+impl<D: DeckCore + Clone> Clone for DeckFpe<D> {
+    fn clone(&self) -> Self {
+        Self { d: self.d.clone() }
+    }
+}
+
+/// Parameters of a numeral string domain, validated by [`Numerals::new`].
+struct Numerals {
+    radix: u32,
+    /// length of the left half
+    u: usize,
+    /// length of the right half, `v >= u`
+    v: usize,
+}
+
+impl Numerals {
+    fn new(radix: u32, x: &[u32]) -> Result<Self, InvalidDomain> {
+        if !(2..=MAX_RADIX).contains(&radix) || x.len() < 2 {
+            return Err(InvalidDomain);
+        }
+        if x.iter().any(|&n| n >= radix) {
+            return Err(InvalidDomain);
+        }
+
+        let n = x.len();
+        let u = n / 2;
+        let v = n - u;
+
+        // both halves must fit in a u64 for the modular arithmetic below
+        let v_exp = u32::try_from(v).map_err(|_| InvalidDomain)?;
+        (radix as u64).checked_pow(v_exp).ok_or(InvalidDomain)?;
+
+        // radix^n >= MIN_DOMAIN
+        let mut size = 1u64;
+        for _ in 0..n {
+            size = size.saturating_mul(radix as u64);
+        }
+        if size < MIN_DOMAIN {
+            return Err(InvalidDomain);
+        }
+
+        Ok(Self { radix, u, v })
+    }
+
+    fn modulus(&self, m: usize) -> u64 {
+        (self.radix as u64).pow(m as u32)
+    }
+
+    /// Interpret the numerals as a big-endian number in this radix.
+    fn num(&self, x: &[u32]) -> u64 {
+        x.iter()
+            .fold(0, |acc, &d| acc * self.radix as u64 + d as u64)
+    }
+
+    /// Write `value` as big-endian numerals in this radix.
+    fn str(&self, mut value: u64, x: &mut [u32]) {
+        for d in x.iter_mut().rev() {
+            *d = (value % self.radix as u64) as u32;
+            value /= self.radix as u64;
+        }
+    }
+}
+
+impl<D: DeckCore + Clone> DeckFpe<D> {
+    pub fn init(key: &[u8]) -> Self {
+        Self {
+            d: <D as DeckCore>::init(key),
+        }
+    }
+
+    /// Apply the tweak to a copy of the keyed deck.
+    fn tweaked(&self, tweak: &[u8]) -> D {
+        let mut d = self.d.clone();
+        apply_padded::<_, 0>(&mut d, tweak, 0);
+        d
+    }
+
+    /// The Feistel round function.
+    ///
+    /// `param` binds the domain: the radix and length for numerals, or `max` for integers.
+    fn round(tweaked: &D, mode: u8, param: u64, round: u8, value: u64) -> u128 {
+        let mut input = [0; 18];
+        input[0] = mode;
+        input[1..9].copy_from_slice(&param.to_le_bytes());
+        input[9] = round;
+        input[10..18].copy_from_slice(&value.to_le_bytes());
+
+        let tag = apply_padded::<_, 0>(&mut tweaked.clone(), &input, 0).read_tag::<U16>();
+        u128::from_le_bytes(tag.into())
+    }
+
+    /// Encrypt a string of numerals in the given radix, in place.
+    ///
+    /// Each numeral must be less than `radix`. The domain `radix^len` must be at least
+    /// [`MIN_DOMAIN`], and each half of the string must fit in a `u64`.
+    pub fn encrypt_numerals(
+        &self,
+        tweak: &[u8],
+        radix: u32,
+        x: &mut [u32],
+    ) -> Result<(), InvalidDomain> {
+        let params = Numerals::new(radix, x)?;
+        let d = self.tweaked(tweak);
+        let param = (radix as u64) << 32 | x.len() as u64;
+
+        let mut a = params.num(&x[..params.u]);
+        let mut b = params.num(&x[params.u..]);
+        for i in 0..ROUNDS {
+            let m = if i % 2 == 0 { params.u } else { params.v };
+            let modulus = params.modulus(m) as u128;
+
+            let y = Self::round(&d, MODE_NUMERALS, param, i, b) % modulus;
+            let c = ((a as u128 + y) % modulus) as u64;
+            a = b;
+            b = c;
+        }
+
+        // after an even number of rounds, `a` has length `u` and `b` has length `v`
+        params.str(a, &mut x[..params.u]);
+        params.str(b, &mut x[params.u..]);
+        Ok(())
+    }
+
+    /// Decrypt a string of numerals in the given radix, in place.
+    pub fn decrypt_numerals(
+        &self,
+        tweak: &[u8],
+        radix: u32,
+        x: &mut [u32],
+    ) -> Result<(), InvalidDomain> {
+        let params = Numerals::new(radix, x)?;
+        let d = self.tweaked(tweak);
+        let param = (radix as u64) << 32 | x.len() as u64;
+
+        let mut a = params.num(&x[..params.u]);
+        let mut b = params.num(&x[params.u..]);
+        for i in (0..ROUNDS).rev() {
+            let m = if i % 2 == 0 { params.u } else { params.v };
+            let modulus = params.modulus(m) as u128;
+
+            let y = Self::round(&d, MODE_NUMERALS, param, i, a) % modulus;
+            let c = ((b as u128 + modulus - y) % modulus) as u64;
+            b = a;
+            a = c;
+        }
+
+        params.str(a, &mut x[..params.u]);
+        params.str(b, &mut x[params.u..]);
+        Ok(())
+    }
+
+    /// Encrypt an integer in the domain `0..=max`.
+    pub fn encrypt_int(&self, tweak: &[u8], max: u64, x: u64) -> Result<u64, InvalidDomain> {
+        if x > max {
+            return Err(InvalidDomain);
+        }
+        if max == 0 {
+            return Ok(x);
+        }
+
+        let d = self.tweaked(tweak);
+        let half = half_bits(max);
+
+        // cycle-walk until we land back in the domain
+        let mut x = x;
+        loop {
+            x = Self::feistel(&d, max, half, x);
+            if x <= max {
+                return Ok(x);
+            }
+        }
+    }
+
+    /// Decrypt an integer in the domain `0..=max`.
+    pub fn decrypt_int(&self, tweak: &[u8], max: u64, x: u64) -> Result<u64, InvalidDomain> {
+        if x > max {
+            return Err(InvalidDomain);
+        }
+        if max == 0 {
+            return Ok(x);
+        }
+
+        let d = self.tweaked(tweak);
+        let half = half_bits(max);
+
+        let mut x = x;
+        loop {
+            x = Self::feistel_inv(&d, max, half, x);
+            if x <= max {
+                return Ok(x);
+            }
+        }
+    }
+
+    /// Balanced Feistel network over `2 * half` bits.
+    fn feistel(d: &D, max: u64, half: u32, x: u64) -> u64 {
+        let mask = (1u64 << half) - 1;
+        let mut l = x >> half;
+        let mut r = x & mask;
+        for i in 0..ROUNDS {
+            let y = Self::round(d, MODE_INTEGER, max, i, r) as u64 & mask;
+            (l, r) = (r, l ^ y);
+        }
+        l << half | r
+    }
+
+    fn feistel_inv(d: &D, max: u64, half: u32, x: u64) -> u64 {
+        let mask = (1u64 << half) - 1;
+        let mut l = x >> half;
+        let mut r = x & mask;
+        for i in (0..ROUNDS).rev() {
+            let y = Self::round(d, MODE_INTEGER, max, i, l) as u64 & mask;
+            (l, r) = (r ^ y, l);
+        }
+        l << half | r
+    }
+}
+
+/// Half the number of bits needed to represent `max`, rounded up.
+fn half_bits(max: u64) -> u32 {
+    let bits = u64::BITS - max.leading_zeros();
+    bits.div_ceil(2)
+}
@@ -0,0 +1,471 @@
+//! Standard Farfalle instances.
+//!
+//! * Xoofff, built on the Xoodoo\[6\] permutation, from the Xoodoo cookbook
+//!   <https://eprint.iacr.org/2018/767>.
+//! * Kravatte (Achouffe), built on Keccak-p\[1600, 6\], from the Farfalle paper
+//!   <https://tosc.iacr.org/index.php/ToSC/article/view/855>. It needs the `kravatte` feature.
+//!
+//! Along with the deck functions themselves, this module defines the parameters of their
+//! Deck-SANE and Deck-SANSE modes, and of the Xoofff wide block cipher.
+
+use core::fmt;
+
+use cipher::AlgorithmName;
+#[cfg(feature = "kravatte")]
+use digest::consts::U200;
+use digest::consts::{U8, U16, U32, U48};
+use hybrid_array::Array;
+
+use crate::Permutation;
+use crate::farfalle::{Farfalle, FarfalleCore};
+use crate::sane::DeckSaneCore;
+use crate::sanse::DeckSanseCore;
+use crate::wbc::WideBlockCipherCore;
+
+/// Parameters of the Xoofff deck function.
+pub struct XoofffCore;
+
+impl FarfalleCore for XoofffCore {
+    type StateSize = U48;
+    type Pb = Xoodoo<6>;
+    type Pc = Xoodoo<6>;
+    type Pd = Xoodoo<6>;
+    type Pe = Xoodoo<6>;
+    type Rc = RollXC;
+    type Re = RollXE;
+}
+
+impl AlgorithmName for XoofffCore {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Xoofff")
+    }
+}
+
+/// The Xoofff deck function.
+pub type Xoofff = Farfalle<XoofffCore>;
+
+/// Parameters of Xoofff-SANE.
+pub struct XoofffSane;
+
+impl DeckSaneCore for XoofffSane {
+    type Core = Xoofff;
+    type TagSize = U16;
+    type Alignnemt = U8;
+}
+
+impl AlgorithmName for XoofffSane {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Xoofff-SANE")
+    }
+}
+
+/// Parameters of Xoofff-SANSE.
+pub struct XoofffSanse;
+
+impl DeckSanseCore for XoofffSanse {
+    type Core = Xoofff;
+    type TagSize = U32;
+}
+
+impl AlgorithmName for XoofffSanse {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Xoofff-SANSE")
+    }
+}
+
+/// Parameters of the Xoofff wide block cipher.
+pub struct XoofffWbc;
+
+impl WideBlockCipherCore for XoofffWbc {
+    type BlockSize = U48;
+    type G = Xoofff;
+    type H = Xoofff;
+    type Alignnemt = U8;
+}
+
+#[cfg(feature = "kravatte")]
+/// Parameters of the Kravatte deck function.
+pub struct KravatteCore;
+
+#[cfg(feature = "kravatte")]
+impl FarfalleCore for KravatteCore {
+    type StateSize = U200;
+    type Pb = KeccakP1600<6>;
+    type Pc = KeccakP1600<6>;
+    type Pd = KeccakP1600<6>;
+    type Pe = KeccakP1600<6>;
+    type Rc = RollKC;
+    type Re = RollKE;
+}
+
+#[cfg(feature = "kravatte")]
+impl AlgorithmName for KravatteCore {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Kravatte")
+    }
+}
+
+#[cfg(feature = "kravatte")]
+/// The Kravatte deck function.
+pub type Kravatte = Farfalle<KravatteCore>;
+
+#[cfg(feature = "kravatte")]
+/// Parameters of Kravatte-SANE.
+pub struct KravatteSane;
+
+#[cfg(feature = "kravatte")]
+impl DeckSaneCore for KravatteSane {
+    type Core = Kravatte;
+    type TagSize = U16;
+    type Alignnemt = U8;
+}
+
+#[cfg(feature = "kravatte")]
+impl AlgorithmName for KravatteSane {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Kravatte-SANE")
+    }
+}
+
+#[cfg(feature = "kravatte")]
+/// Parameters of Kravatte-SANSE.
+pub struct KravatteSanse;
+
+#[cfg(feature = "kravatte")]
+impl DeckSanseCore for KravatteSanse {
+    type Core = Kravatte;
+    type TagSize = U32;
+}
+
+#[cfg(feature = "kravatte")]
+impl AlgorithmName for KravatteSanse {
+    fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Kravatte-SANSE")
+    }
+}
+
+fn load_lanes(block: &Array<u8, U48>) -> [u32; 12] {
+    let mut lanes = [0; 12];
+    for (lane, bytes) in lanes.iter_mut().zip(block.chunks_exact(4)) {
+        *lane = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    lanes
+}
+
+fn store_lanes(lanes: &[u32; 12], block: &mut Array<u8, U48>) {
+    for (lane, bytes) in lanes.iter().zip(block.chunks_exact_mut(4)) {
+        bytes.copy_from_slice(&lane.to_le_bytes());
+    }
+}
+
+/// Xoodoo\[R\]: the last `R` rounds of Xoodoo\[12\], on little-endian lanes.
+#[derive(Default, Clone, Copy)]
+pub struct Xoodoo<const R: usize> {}
+
+impl<const R: usize> Permutation for Xoodoo<R> {
+    type Size = U48;
+
+    fn permute(block: &mut Array<u8, Self::Size>) {
+        const { assert!(R <= MAX_ROUNDS, "Xoodoo has at most 12 rounds") };
+        let mut b = load_lanes(block);
+        xoodoo::<R>(&mut b);
+        store_lanes(&b, block);
+    }
+}
+
+/// The Xoofff compression rolling function.
+#[derive(Default, Clone, Copy)]
+pub struct RollXC {}
+
+impl Permutation for RollXC {
+    type Size = U48;
+
+    fn permute(block: &mut Array<u8, Self::Size>) {
+        let mut b = load_lanes(block);
+        rollxc(&mut b);
+        store_lanes(&b, block);
+    }
+}
+
+/// The Xoofff expansion rolling function.
+#[derive(Default, Clone, Copy)]
+pub struct RollXE {}
+
+impl Permutation for RollXE {
+    type Size = U48;
+
+    fn permute(block: &mut Array<u8, Self::Size>) {
+        let mut b = load_lanes(block);
+        rollxe(&mut b);
+        store_lanes(&b, block);
+    }
+}
+
+/// <https://docs.rs/xoodoo-p/0.1.0/src/xoodoo_p/lib.rs.html>
+const MAX_ROUNDS: usize = 12;
+fn xoodoo<const R: usize>(st: &mut [u32; 12]) {
+    // Load lanes into registers.
+    let mut a00 = st[0];
+    let mut a01 = st[1];
+    let mut a02 = st[2];
+    let mut a03 = st[3];
+    let mut a10 = st[4];
+    let mut a11 = st[5];
+    let mut a12 = st[6];
+    let mut a13 = st[7];
+    let mut a20 = st[8];
+    let mut a21 = st[9];
+    let mut a22 = st[10];
+    let mut a23 = st[11];
+
+    // Perform last R rounds.
+    for &round_key in &ROUND_KEYS[MAX_ROUNDS - R..MAX_ROUNDS] {
+        let p0 = a00 ^ a10 ^ a20;
+        let p1 = a01 ^ a11 ^ a21;
+        let p2 = a02 ^ a12 ^ a22;
+        let p3 = a03 ^ a13 ^ a23;
+
+        let e0 = p3.rotate_left(5) ^ p3.rotate_left(14);
+        let e1 = p0.rotate_left(5) ^ p0.rotate_left(14);
+        let e2 = p1.rotate_left(5) ^ p1.rotate_left(14);
+        let e3 = p2.rotate_left(5) ^ p2.rotate_left(14);
+
+        let tmp0 = e0 ^ a00 ^ round_key;
+        let tmp1 = e1 ^ a01;
+        let tmp2 = e2 ^ a02;
+        let tmp3 = e3 ^ a03;
+        let tmp4 = e3 ^ a13;
+        let tmp5 = e0 ^ a10;
+        let tmp6 = e1 ^ a11;
+        let tmp7 = e2 ^ a12;
+        let tmp8 = (e0 ^ a20).rotate_left(11);
+        let tmp9 = (e1 ^ a21).rotate_left(11);
+        let tmp10 = (e2 ^ a22).rotate_left(11);
+        let tmp11 = (e3 ^ a23).rotate_left(11);
+
+        a00 = (!tmp4 & tmp8) ^ tmp0;
+        a01 = (!tmp5 & tmp9) ^ tmp1;
+        a02 = (!tmp6 & tmp10) ^ tmp2;
+        a03 = (!tmp7 & tmp11) ^ tmp3;
+
+        a10 = ((!tmp8 & tmp0) ^ tmp4).rotate_left(1);
+        a11 = ((!tmp9 & tmp1) ^ tmp5).rotate_left(1);
+        a12 = ((!tmp10 & tmp2) ^ tmp6).rotate_left(1);
+        a13 = ((!tmp11 & tmp3) ^ tmp7).rotate_left(1);
+
+        a20 = ((!tmp2 & tmp6) ^ tmp10).rotate_left(8);
+        a21 = ((!tmp3 & tmp7) ^ tmp11).rotate_left(8);
+        a22 = ((!tmp0 & tmp4) ^ tmp8).rotate_left(8);
+        a23 = ((!tmp1 & tmp5) ^ tmp9).rotate_left(8);
+    }
+
+    // Load registers into lanes.
+    st[0] = a00;
+    st[1] = a01;
+    st[2] = a02;
+    st[3] = a03;
+    st[4] = a10;
+    st[5] = a11;
+    st[6] = a12;
+    st[7] = a13;
+    st[8] = a20;
+    st[9] = a21;
+    st[10] = a22;
+    st[11] = a23;
+}
+
+const ROUND_KEYS: [u32; MAX_ROUNDS] = [
+    0x00000058, 0x00000038, 0x000003C0, 0x000000D0, 0x00000120, 0x00000014, 0x00000060, 0x0000002C,
+    0x00000380, 0x000000F0, 0x000001A0, 0x00000012,
+];
+
+fn rollxc(st: &mut [u32; 12]) {
+    // Load lanes into registers.
+    let mut a00 = st[0];
+    let mut a01 = st[1];
+    let mut a02 = st[2];
+    let mut a03 = st[3];
+    let mut a10 = st[4];
+    let mut a11 = st[5];
+    let mut a12 = st[6];
+    let mut a13 = st[7];
+    let mut a20 = st[8];
+    let mut a21 = st[9];
+    let mut a22 = st[10];
+    let mut a23 = st[11];
+
+    // A00 <- A00 + (A00 << 13) + (A10 <<< 3)
+    a00 = a00 ^ (a00 << 13) ^ a10.rotate_left(3);
+
+    // B <- A0 <<< (3, 0)
+    let b0 = a03;
+    let b1 = a00;
+    let b2 = a01;
+    let b3 = a02;
+
+    // A0 <- A1
+    a00 = a10;
+    a01 = a11;
+    a02 = a12;
+    a03 = a13;
+
+    // A1 <- A2
+    a10 = a20;
+    a11 = a21;
+    a12 = a22;
+    a13 = a23;
+
+    // A2 <- B
+    a20 = b0;
+    a21 = b1;
+    a22 = b2;
+    a23 = b3;
+
+    // Load registers into lanes.
+    st[0] = a00;
+    st[1] = a01;
+    st[2] = a02;
+    st[3] = a03;
+    st[4] = a10;
+    st[5] = a11;
+    st[6] = a12;
+    st[7] = a13;
+    st[8] = a20;
+    st[9] = a21;
+    st[10] = a22;
+    st[11] = a23;
+}
+
+fn rollxe(st: &mut [u32; 12]) {
+    // Load lanes into registers.
+    let mut a00 = st[0];
+    let mut a01 = st[1];
+    let mut a02 = st[2];
+    let mut a03 = st[3];
+    let mut a10 = st[4];
+    let mut a11 = st[5];
+    let mut a12 = st[6];
+    let mut a13 = st[7];
+    let mut a20 = st[8];
+    let mut a21 = st[9];
+    let mut a22 = st[10];
+    let mut a23 = st[11];
+
+    // A00 <- A10 . A20 + (A00 <<< 5) + (A10 <<< 13) + 0x00000007
+    a00 = a10 & a20 ^ (a00.rotate_left(5)) ^ a10.rotate_left(13) ^ 0x00000007;
+
+    // B <- A0 <<< (3, 0)
+    let b0 = a03;
+    let b1 = a00;
+    let b2 = a01;
+    let b3 = a02;
+
+    // A0 <- A1
+    a00 = a10;
+    a01 = a11;
+    a02 = a12;
+    a03 = a13;
+
+    // A1 <- A2
+    a10 = a20;
+    a11 = a21;
+    a12 = a22;
+    a13 = a23;
+
+    // A2 <- B
+    a20 = b0;
+    a21 = b1;
+    a22 = b2;
+    a23 = b3;
+
+    // Load registers into lanes.
+    st[0] = a00;
+    st[1] = a01;
+    st[2] = a02;
+    st[3] = a03;
+    st[4] = a10;
+    st[5] = a11;
+    st[6] = a12;
+    st[7] = a13;
+    st[8] = a20;
+    st[9] = a21;
+    st[10] = a22;
+    st[11] = a23;
+}
+
+#[cfg(feature = "kravatte")]
+/// Keccak-p\[1600, R\]: the last `R` rounds of Keccak-f\[1600\].
+#[derive(Default, Clone, Copy)]
+pub struct KeccakP1600<const R: usize> {}
+
+#[cfg(feature = "kravatte")]
+impl<const R: usize> Permutation for KeccakP1600<R> {
+    type Size = U200;
+
+    fn permute(block: &mut Array<u8, Self::Size>) {
+        const { assert!(R <= 24, "Keccak-f[1600] has 24 rounds") };
+        let mut a = load_keccak_lanes(block);
+        keccak::p1600(&mut a, R);
+        store_keccak_lanes(&a, block);
+    }
+}
+
+#[cfg(feature = "kravatte")]
+/// The Kravatte compression rolling function, on the last plane.
+#[derive(Default, Clone, Copy)]
+pub struct RollKC {}
+
+#[cfg(feature = "kravatte")]
+impl Permutation for RollKC {
+    type Size = U200;
+
+    fn permute(block: &mut Array<u8, Self::Size>) {
+        let mut a = load_keccak_lanes(block);
+        let x = &mut a[20..25];
+
+        // x0 <- (x0 <<< 7) + x1 + (x1 >> 3), then shift the lanes by one
+        let x0 = x[0].rotate_left(7) ^ x[1] ^ (x[1] >> 3);
+        x.copy_within(1.., 0);
+        x[4] = x0;
+
+        store_keccak_lanes(&a, block);
+    }
+}
+
+#[cfg(feature = "kravatte")]
+/// The Kravatte expansion rolling function, on the last two planes.
+#[derive(Default, Clone, Copy)]
+pub struct RollKE {}
+
+#[cfg(feature = "kravatte")]
+impl Permutation for RollKE {
+    type Size = U200;
+
+    fn permute(block: &mut Array<u8, Self::Size>) {
+        let mut a = load_keccak_lanes(block);
+        let x = &mut a[15..25];
+
+        // x0 <- (x0 <<< 7) + (x1 <<< 18) + (x2 . (x1 >> 1)), then shift the lanes by one
+        let x0 = x[0].rotate_left(7) ^ x[1].rotate_left(18) ^ (x[2] & (x[1] >> 1));
+        x.copy_within(1.., 0);
+        x[9] = x0;
+
+        store_keccak_lanes(&a, block);
+    }
+}
+
+#[cfg(feature = "kravatte")]
+fn load_keccak_lanes(block: &Array<u8, U200>) -> [u64; 25] {
+    let mut lanes = [0; 25];
+    for (lane, bytes) in lanes.iter_mut().zip(block.chunks_exact(8)) {
+        *lane = u64::from_le_bytes(bytes.try_into().unwrap());
+    }
+    lanes
+}
+
+#[cfg(feature = "kravatte")]
+fn store_keccak_lanes(lanes: &[u64; 25], block: &mut Array<u8, U200>) {
+    for (lane, bytes) in lanes.iter().zip(block.chunks_exact_mut(8)) {
+        bytes.copy_from_slice(&lane.to_le_bytes());
+    }
+}
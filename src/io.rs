@@ -0,0 +1,257 @@
+//! [`std::io`] adapters for framed session encryption.
+//!
+//! [`SessionWriter`] splits the written data into frames and encrypts each as one message of a
+//! [`SessionAead`] session, and [`SessionReader`] decrypts them again. Each frame on the wire is:
+//!
+//! | field      | size     | contents                                                      |
+//! |------------|----------|---------------------------------------------------------------|
+//! | header     | 4        | frame length as `u32` LE, top bit set on the last frame       |
+//! | ciphertext | length   | the encrypted frame                                           |
+//! | tag        | `S::Tag` | the tag of the frame, with the header as associated data      |
+//!
+//! The session binds every frame to all the frames before it, and the last frame is marked in
+//! its authenticated header, so reordered, dropped or truncated frames are all detected. Both
+//! adapters fail closed: after any error, every further call returns an error.
+
+use alloc::vec::Vec;
+use std::io::{self, Read, Write};
+
+use hybrid_array::Array;
+use inout::InOutBuf;
+
+use crate::SessionAead;
+
+/// Default size of the frames written by [`SessionWriter`], and the largest frame accepted by
+/// [`SessionReader`].
+pub const DEFAULT_FRAME_SIZE: usize = 16 * 1024;
+
+/// Largest possible frame size.
+pub const MAX_FRAME_SIZE: usize = FINAL as usize - 1;
+
+/// Flag in the header of the last frame.
+const FINAL: u32 = 1 << 31;
+
+/// Length of the header in front of every frame.
+///
+/// The framing is shared with the codec module, which never sets the flag of the last frame.
+pub(crate) const HEADER_LEN: usize = 4;
+
+/// Encode the header of a frame of `len` bytes, which must be at most [`MAX_FRAME_SIZE`].
+pub(crate) fn encode_header(len: usize, last: bool) -> [u8; HEADER_LEN] {
+    debug_assert!(len <= MAX_FRAME_SIZE);
+    let flag = if last { FINAL } else { 0 };
+    (len as u32 | flag).to_le_bytes()
+}
+
+/// Decode a frame header into the frame length and whether it is the last frame, rejecting
+/// frames longer than `max_frame_size`.
+pub(crate) fn decode_header(
+    header: [u8; HEADER_LEN],
+    max_frame_size: usize,
+) -> io::Result<(usize, bool)> {
+    let value = u32::from_le_bytes(header);
+    let len = (value & !FINAL) as usize;
+    if len > max_frame_size {
+        return Err(frame_too_large());
+    }
+    Ok((len, value & FINAL != 0))
+}
+
+pub(crate) fn frame_too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "session frame too large")
+}
+
+pub(crate) fn authentication_failed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "session frame failed authentication",
+    )
+}
+
+pub(crate) fn poisoned() -> io::Error {
+    io::Error::other("session stream failed earlier")
+}
+
+/// Encrypts everything written to it into frames on the inner writer.
+///
+/// [`SessionWriter::finish`] must be called to write the last frame, otherwise the reader
+/// reports the stream as truncated.
+pub struct SessionWriter<W: Write, S: SessionAead> {
+    inner: W,
+    session: S,
+    /// plaintext of the frame being filled
+    buffer: Vec<u8>,
+    frame_size: usize,
+    poisoned: bool,
+}
+
+impl<W: Write, S: SessionAead> SessionWriter<W, S> {
+    pub fn new(inner: W, session: S) -> Self {
+        Self::with_frame_size(inner, session, DEFAULT_FRAME_SIZE)
+    }
+
+    /// # Panics
+    ///
+    /// If `frame_size` is zero or larger than [`MAX_FRAME_SIZE`].
+    pub fn with_frame_size(inner: W, session: S, frame_size: usize) -> Self {
+        assert!((1..=MAX_FRAME_SIZE).contains(&frame_size));
+        Self {
+            inner,
+            session,
+            buffer: Vec::with_capacity(frame_size),
+            frame_size,
+            poisoned: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Encrypt and write the buffered data as one frame.
+    fn write_frame(&mut self, last: bool) -> io::Result<()> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        // the session moves forward here, so a failed write breaks the stream
+        self.poisoned = true;
+
+        let header = encode_header(self.buffer.len(), last);
+        let tag = self
+            .session
+            .encrypt_inout_detached(&header, InOutBuf::from(&mut self.buffer[..]));
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&self.buffer)?;
+        self.inner.write_all(&tag)?;
+        self.buffer.clear();
+
+        self.poisoned = false;
+        Ok(())
+    }
+
+    /// Write the last frame and flush, returning the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_frame(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write, S: SessionAead> Write for SessionWriter<W, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        let n = buf.len().min(self.frame_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == self.frame_size {
+            self.write_frame(false)?;
+        }
+        Ok(n)
+    }
+
+    /// Write the buffered data as a frame, if there is any, and flush the inner writer.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.write_frame(false)?;
+        }
+        self.inner.flush()
+    }
+}
+
+/// Decrypts the frames written by a [`SessionWriter`] from the inner reader.
+///
+/// Plaintext is only returned once the frame containing it has been authenticated.
+pub struct SessionReader<R: Read, S: SessionAead> {
+    inner: R,
+    session: S,
+    /// plaintext of the current frame
+    buffer: Vec<u8>,
+    /// position of the unread plaintext in `buffer`
+    pos: usize,
+    max_frame_size: usize,
+    finished: bool,
+    poisoned: bool,
+}
+
+impl<R: Read, S: SessionAead> SessionReader<R, S> {
+    pub fn new(inner: R, session: S) -> Self {
+        Self::with_max_frame_size(inner, session, DEFAULT_FRAME_SIZE)
+    }
+
+    /// Frames larger than `max_frame_size` are rejected before they are read.
+    pub fn with_max_frame_size(inner: R, session: S, max_frame_size: usize) -> Self {
+        Self {
+            inner,
+            session,
+            buffer: Vec::new(),
+            pos: 0,
+            max_frame_size,
+            finished: false,
+            poisoned: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Whether the last frame has been read.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Read, authenticate and decrypt the next frame into the buffer.
+    fn read_frame(&mut self) -> io::Result<()> {
+        // cleared once the frame has been authenticated
+        self.poisoned = true;
+        self.buffer.clear();
+        self.pos = 0;
+
+        let mut header = [0; HEADER_LEN];
+        self.inner.read_exact(&mut header)?;
+        let (len, last) = decode_header(header, self.max_frame_size)?;
+
+        self.buffer.resize(len, 0);
+        self.inner.read_exact(&mut self.buffer)?;
+        let mut tag = Array::<u8, S::Tag>::default();
+        self.inner.read_exact(&mut tag)?;
+
+        if self
+            .session
+            .decrypt_inout_detached(&header, InOutBuf::from(&mut self.buffer[..]), &tag)
+            .is_err()
+        {
+            self.buffer.clear();
+            return Err(authentication_failed());
+        }
+
+        self.finished = last;
+        self.poisoned = false;
+        Ok(())
+    }
+}
+
+impl<R: Read, S: SessionAead> Read for SessionReader<R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pos == self.buffer.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            // an end of file before the last frame is reported as `UnexpectedEof`
+            self.read_frame()?;
+        }
+
+        let n = buf.len().min(self.buffer.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
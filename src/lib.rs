@@ -1,12 +1,44 @@
-use hybrid_array::{Array, ArraySize};
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use hybrid_array::{Array, ArraySize, typenum::Unsigned};
 use inout::InOutBuf;
 
+pub mod channel;
+#[cfg(feature = "codec")]
+pub mod codec;
 pub mod core_api;
+pub mod datagram;
 pub mod dec;
 pub mod deck;
+#[cfg(feature = "alloc")]
+pub mod dynamic;
 pub mod farfalle;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod file;
+pub mod fpe;
+pub mod instances;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod params;
+pub mod ratchet;
+#[cfg(feature = "reference")]
+pub mod reference;
 pub mod sane;
 pub mod sanse;
+pub mod sector;
+#[cfg(feature = "std")]
+pub mod seekable;
+pub mod snapshot;
 pub mod wbc;
 
 pub trait Permutation {
@@ -24,44 +56,147 @@ pub trait SessionAead {
         &mut self,
         associated_data: &[u8],
         buffer: InOutBuf<'_, '_, u8>,
-    ) -> Array<u8, Self::Tag>;
+    ) -> Array<u8, Self::Tag> {
+        self.encrypt_vectored(&[associated_data], buffer)
+    }
 
     /// Decrypt the data in the provided [`InOutBuf`], returning an error in the event the
     /// provided authentication tag is invalid for the given ciphertext (i.e. ciphertext
     /// is modified/unauthentic)
     ///
-    /// This also moves the session state forward, but must be discarded if there is a tag error.
+    /// This also moves the session state forward. If there is a tag error the session state
+    /// is left untouched, so the message can be dropped and the session continued.
     fn decrypt_inout_detached(
         &mut self,
         associated_data: &[u8],
         buffer: InOutBuf<'_, '_, u8>,
         tag: &Array<u8, Self::Tag>,
+    ) -> aead::Result<()> {
+        self.decrypt_vectored(&[associated_data], buffer, tag)
+    }
+
+    /// Like [`SessionAead::encrypt_inout_detached`], but with the associated data split into
+    /// several fields. Each field is absorbed as its own string, so field boundaries are
+    /// authenticated. A single field is equivalent to the non-vectored method.
+    fn encrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+    ) -> Array<u8, Self::Tag>;
+
+    /// Like [`SessionAead::decrypt_inout_detached`], but with the associated data split into
+    /// several fields. See [`SessionAead::encrypt_vectored`].
+    fn decrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &Array<u8, Self::Tag>,
     ) -> aead::Result<()>;
+
+    /// Encrypt the message in the buffer in-place, appending the authentication tag.
+    ///
+    /// Space for the tag is reserved before encrypting, so if the buffer cannot grow
+    /// the session state is left untouched.
+    fn encrypt_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut impl aead::Buffer,
+    ) -> aead::Result<()> {
+        let msg_len = buffer.len();
+        buffer.extend_from_slice(&Array::<u8, Self::Tag>::default())?;
+
+        let (msg, tag_dst) = buffer.as_mut().split_at_mut(msg_len);
+        let tag = self.encrypt_inout_detached(associated_data, msg.into());
+        tag_dst.copy_from_slice(&tag);
+        Ok(())
+    }
+
+    /// Decrypt the message in the buffer in-place, returning an error in the event the
+    /// appended authentication tag is invalid.
+    ///
+    /// The buffer is truncated to the length of the plaintext on success.
+    fn decrypt_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut impl aead::Buffer,
+    ) -> aead::Result<()> {
+        let tagless_len = buffer
+            .len()
+            .checked_sub(Self::Tag::USIZE)
+            .ok_or(aead::Error)?;
+
+        let (msg, tag) = buffer.as_mut().split_at_mut(tagless_len);
+        let tag = Array::<u8, Self::Tag>::try_from(&*tag).expect("tag length mismatch");
+        self.decrypt_inout_detached(associated_data, msg.into(), &tag)?;
+
+        buffer.truncate(tagless_len);
+        Ok(())
+    }
+
+    /// Encrypt the plaintext, returning the ciphertext with the authentication tag appended.
+    #[cfg(feature = "alloc")]
+    fn encrypt(&mut self, associated_data: &[u8], plaintext: &[u8]) -> aead::Result<Vec<u8>> {
+        let mut out = alloc::vec![0; plaintext.len() + Self::Tag::USIZE];
+
+        let (msg, tag_dst) = out.split_at_mut(plaintext.len());
+        let buffer = InOutBuf::new(plaintext, msg).expect("buffer lengths match");
+        let tag = self.encrypt_inout_detached(associated_data, buffer);
+        tag_dst.copy_from_slice(&tag);
+        Ok(out)
+    }
+
+    /// Decrypt the ciphertext with its appended authentication tag, returning the plaintext.
+    #[cfg(feature = "alloc")]
+    fn decrypt(&mut self, associated_data: &[u8], ciphertext: &[u8]) -> aead::Result<Vec<u8>> {
+        let tagless_len = ciphertext
+            .len()
+            .checked_sub(Self::Tag::USIZE)
+            .ok_or(aead::Error)?;
+
+        let (ciphertext, tag) = ciphertext.split_at(tagless_len);
+        let tag = Array::<u8, Self::Tag>::try_from(tag).expect("tag length mismatch");
+
+        let mut out = alloc::vec![0; tagless_len];
+        let buffer = InOutBuf::new(ciphertext, &mut out).expect("buffer lengths match");
+        self.decrypt_inout_detached(associated_data, buffer, &tag)?;
+        Ok(out)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use digest::consts::{U16, U32, U48};
+    use std::prelude::v1::*;
+    use std::vec;
+
+    use core::fmt;
+
+    use cipher::AlgorithmName;
+    use digest::consts::{U8, U16, U32, U48};
+    use digest::typenum::Unsigned;
+    use digest::{Update, XofReader};
     use inout::InOutBuf;
+    use subtle::ConstantTimeEq;
 
     use crate::{
-        Permutation, SessionAead,
-        farfalle::{Farfalle, FarfalleCore},
-        sane::{DeckSane, DeckSaneCore},
-        sanse::{DeckSanse, DeckSanseCore},
+        SessionAead,
+        channel::{Role, SessionChannel},
+        core_api::CoreWrapper,
+        datagram::{DatagramReceiver, DatagramSender, DeckDatagramCore, ReplayWindow, WINDOW_SIZE},
+        deck::Deck,
+        fpe::{DeckFpe, InvalidDomain},
+        instances::{self, Xoofff},
+        params::{InvalidParameters, MAX_ALIGNMENT, MAX_TAG_SIZE, MIN_TAG_SIZE},
+        ratchet::{AutoRatchet, Ratchet, RatchetPolicy},
+        sane::{DeckSane, DeckSaneCore, RuntimeDeckSane},
+        sanse::{DeckSanse, DeckSanseCore, RuntimeDeckSanse},
+        sector::SectorCipher,
+        wbc::{
+            RuntimeWideBlockCipherAuthenticated, Verified, WideBlockCipher,
+            WideBlockCipherAuthenticated, WideBlockCipherCore, ZeroExpansion,
+        },
     };
 
-    struct XoofffCore;
-    impl FarfalleCore for XoofffCore {
-        type StateSize = U48;
-        type Pb = Xoodoo<6>;
-        type Pc = Xoodoo<6>;
-        type Pd = Xoodoo<6>;
-        type Pe = Xoodoo<6>;
-        type Rc = RollXC;
-        type Re = RollXE;
-    }
-    type XoofffDeckCore = Farfalle<XoofffCore>;
+    type XoofffDeckCore = Xoofff;
     // type XoofffDeck = crate::deck::CoreWrapper<XoofffDeckCore>;
 
     struct XoofffSaneCore;
@@ -71,6 +206,12 @@ mod tests {
         type Alignnemt = U32;
     }
 
+    impl AlgorithmName for XoofffSaneCore {
+        fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("Xoofff-SANE")
+        }
+    }
+
     type XoofffSane = DeckSane<XoofffSaneCore>;
 
     #[test]
@@ -95,14 +236,898 @@ mod tests {
         assert_eq!(msg2, *b"xyzw");
     }
 
+    #[test]
+    fn check_forgery_recovery() {
+        let key = [0; 32];
+        let iv = [0; 16];
+        let mut enc = XoofffSane::init(&key, &iv);
+        let mut dec = XoofffSane::init(&key, &iv);
+        check_session_recovery(&mut enc, &mut dec);
+
+        let mut enc = XoofffSanse::init(&key);
+        let mut dec = XoofffSanse::init(&key);
+        check_session_recovery(&mut enc, &mut dec);
+    }
+
+    /// A forged message is rejected without disturbing the receiving session.
+    fn check_session_recovery<S: SessionAead>(enc: &mut S, dec: &mut S) {
+        let messages: [(&[u8], &[u8]); 3] = [(b"foo", b"abcd"), (b"", b"xyzw"), (b"bar", b"")];
+        for (ad, msg) in messages {
+            let mut buffer = msg.to_vec();
+            let tag = enc.encrypt_inout_detached(ad, InOutBuf::from(&mut buffer[..]));
+            let ciphertext = buffer.clone();
+
+            let mut forged_tag = tag.clone();
+            forged_tag[0] ^= 1;
+            dec.decrypt_inout_detached(ad, InOutBuf::from(&mut buffer[..]), &forged_tag)
+                .unwrap_err();
+            assert_eq!(buffer, ciphertext);
+
+            if !buffer.is_empty() {
+                buffer[0] ^= 1;
+                dec.decrypt_inout_detached(ad, InOutBuf::from(&mut buffer[..]), &tag)
+                    .unwrap_err();
+                buffer[0] ^= 1;
+                assert_eq!(buffer, ciphertext);
+            }
+
+            dec.decrypt_inout_detached(ad, InOutBuf::from(&mut buffer[..]), &tag)
+                .unwrap();
+            assert_eq!(buffer, msg);
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn check_zeroize() {
+        use crate::{deck::DeckCore, snapshot::SerializeState};
+        use zeroize::Zeroize;
+
+        let mut d = XoofffDeckCore::init(&[1; 32]);
+        d.zeroize();
+
+        let mut state = [0xff; <XoofffDeckCore as SerializeState>::STATE_LEN];
+        d.serialize_state(&mut state);
+        assert!(state.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn check_sane_keystream_offset() {
+        use digest::consts::U128;
+
+        // an offset of more than a block past the tag
+        struct WideAlignment;
+        impl DeckSaneCore for WideAlignment {
+            type Core = XoofffDeckCore;
+            type TagSize = U16;
+            type Alignnemt = U128;
+        }
+
+        let key = [3; 32];
+        let iv = [4; 16];
+        let mut keystream = [0; 128 + 64];
+        let mut deck = CoreWrapper::<XoofffDeckCore>::init(&key);
+        deck.update(&iv);
+        deck.finalize_deck().read(&mut keystream);
+
+        // the first message is encrypted with the keystream of the IV, from the aligned offset
+        let mut buffer = [0; 64];
+        DeckSane::<WideAlignment>::init(&key, &iv)
+            .encrypt_inout_detached(b"", InOutBuf::from(&mut buffer[..]));
+        assert_eq!(buffer[..], keystream[128..]);
+    }
+
+    #[test]
+    fn check_sane_streaming() {
+        let key = [0; 32];
+        let iv = [0; 16];
+        let mut enc = XoofffSane::init(&key, &iv);
+        let mut enc_stream = XoofffSane::init(&key, &iv);
+        let mut dec_stream = XoofffSane::init(&key, &iv);
+
+        let msg: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let cases: [(&[u8], usize, usize); 6] = [
+            (b"foo", 1000, 1),
+            (b"", 1000, 7),
+            (b"bar", 0, 1),
+            (b"", 0, 1),
+            (b"", 500, 48),
+            (b"baz", 100, 64),
+        ];
+        for (ad, len, chunk) in cases {
+            let mut expected = msg[..len].to_vec();
+            let tag = enc.encrypt_inout_detached(ad, InOutBuf::from(&mut expected[..]));
+
+            let mut ciphertext = msg[..len].to_vec();
+            let mut stream = enc_stream.begin_encrypt(ad);
+            for c in ciphertext.chunks_mut(chunk) {
+                stream.update(InOutBuf::from(c));
+            }
+            assert_eq!(stream.finish(), tag);
+            assert_eq!(ciphertext, expected);
+
+            // a forged tag leaves the session untouched
+            let mut plaintext = ciphertext.clone();
+            let mut stream = dec_stream.begin_decrypt(ad);
+            stream.update(InOutBuf::from(&mut plaintext[..]));
+            let mut forged_tag = tag.clone();
+            forged_tag[0] ^= 1;
+            stream.finish(&forged_tag).unwrap_err();
+
+            let mut plaintext = ciphertext.clone();
+            let mut stream = dec_stream.begin_decrypt(ad);
+            for c in plaintext.chunks_mut(chunk) {
+                stream.update(InOutBuf::from(c));
+            }
+            stream.finish(&tag).unwrap();
+            assert_eq!(plaintext, msg[..len]);
+        }
+    }
+
+    #[test]
+    fn check_sane_streaming_dropped() {
+        let key = [0; 32];
+        let iv = [0; 16];
+        let mut session = XoofffSane::init(&key, &iv);
+
+        // an encryptor dropped before producing ciphertext leaves the session untouched
+        drop(session.begin_encrypt(b"ad"));
+        let mut expected = [0; 32];
+        XoofffSane::init(&key, &iv).encrypt_inout_detached(b"", InOutBuf::from(&mut expected[..]));
+
+        // one dropped after producing ciphertext moves it forward, so the keystream is not reused
+        let mut first = [0; 32];
+        let mut stream = session.begin_encrypt(b"");
+        stream.update(InOutBuf::from(&mut first[..]));
+        drop(stream);
+        assert_eq!(first, expected);
+
+        let mut second = [0; 32];
+        session.encrypt_inout_detached(b"", InOutBuf::from(&mut second[..]));
+        assert_ne!(second, first);
+    }
+
+    #[test]
+    fn check_vectored() {
+        let key = [0; 32];
+        let iv = [0; 16];
+        check_vectored_session(|| XoofffSane::init(&key, &iv));
+        check_vectored_session(|| XoofffSanse::init(&key));
+    }
+
+    fn check_vectored_session<S: SessionAead>(init: impl Fn() -> S) {
+        let tag_of = |ad: &[&[u8]], msg: &[u8]| {
+            let mut buffer = msg.to_vec();
+            init().encrypt_vectored(ad, InOutBuf::from(&mut buffer[..]))
+        };
+
+        for msg in [&b""[..], b"abcd"] {
+            // a single field is the same as the non-vectored API
+            let mut buffer = msg.to_vec();
+            let tag = init().encrypt_inout_detached(b"foo", InOutBuf::from(&mut buffer[..]));
+            assert_eq!(tag_of(&[b"foo"], msg), tag);
+            assert_eq!(tag_of(&[], msg), tag_of(&[b""], msg));
+
+            // field boundaries are authenticated
+            assert_ne!(tag_of(&[b"ab", b"c"], msg), tag_of(&[b"a", b"bc"], msg));
+            assert_ne!(tag_of(&[b"", b"abc"], msg), tag_of(&[b"abc"], msg));
+            assert_ne!(tag_of(&[b"abc", b""], msg), tag_of(&[b"abc"], msg));
+            assert_ne!(tag_of(&[b"", b""], msg), tag_of(&[b""], msg));
+        }
+
+        let mut enc = init();
+        let mut dec = init();
+        let ad: [&[u8]; 3] = [b"version", b"", b"header"];
+        let mut buffer = *b"abcd";
+        let tag = enc.encrypt_vectored(&ad, InOutBuf::from(&mut buffer[..]));
+        dec.decrypt_vectored(&ad[..2], InOutBuf::from(&mut buffer[..]), &tag)
+            .unwrap_err();
+        dec.decrypt_vectored(&ad, InOutBuf::from(&mut buffer[..]), &tag)
+            .unwrap();
+        assert_eq!(buffer, *b"abcd");
+    }
+
+    #[test]
+    fn check_attached() {
+        let key = [0; 32];
+        let iv = [0; 16];
+        check_attached_session(|| XoofffSane::init(&key, &iv));
+        check_attached_session(|| XoofffSanse::init(&key));
+    }
+
+    fn check_attached_session<S: SessionAead>(init: impl Fn() -> S) {
+        let mut enc = init();
+        let mut dec = init();
+        let mut detached = init();
+
+        for msg in [&b""[..], b"abcd", &[7; 100]] {
+            // the tag is appended to the detached ciphertext
+            let mut expected = msg.to_vec();
+            let tag = detached.encrypt_inout_detached(b"ad", InOutBuf::from(&mut expected[..]));
+            expected.extend_from_slice(&tag);
+
+            let mut buffer = msg.to_vec();
+            enc.encrypt_in_place(b"ad", &mut buffer).unwrap();
+            assert_eq!(buffer, expected);
+
+            // a flipped bit fails and leaves the buffer and session untouched
+            buffer[0] ^= 1;
+            dec.decrypt_in_place(b"ad", &mut buffer).unwrap_err();
+            assert_eq!(buffer.len(), msg.len() + S::Tag::USIZE);
+            buffer[0] ^= 1;
+
+            dec.decrypt_in_place(b"ad", &mut buffer).unwrap();
+            assert_eq!(buffer, msg);
+        }
+
+        // shorter than a tag
+        let mut buffer = vec![0; S::Tag::USIZE - 1];
+        dec.decrypt_in_place(b"ad", &mut buffer).unwrap_err();
+
+        #[cfg(feature = "alloc")]
+        {
+            let ciphertext = enc.encrypt(b"ad", b"abcd").unwrap();
+            assert_eq!(dec.decrypt(b"ad", &ciphertext).unwrap(), b"abcd");
+            dec.decrypt(b"ad", &ciphertext[..S::Tag::USIZE - 1])
+                .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn check_ratchet() {
+        let key = [0; 32];
+        let iv = [0; 16];
+        check_ratchet_session(|| XoofffSane::init(&key, &iv));
+        check_ratchet_session(|| XoofffSanse::init(&key));
+    }
+
+    fn check_ratchet_session<S: SessionAead + Ratchet>(init: impl Fn() -> S) {
+        let mut enc = init();
+        let mut dec = init();
+        let mut unratcheted = init();
+
+        let tag_of = |s: &mut S, msg: &[u8]| {
+            let mut buffer = msg.to_vec();
+            s.encrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]))
+        };
+
+        let mut buffer = *b"abcd";
+        let tag = enc.encrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]));
+        dec.decrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]), &tag)
+            .unwrap();
+        tag_of(&mut unratcheted, b"abcd");
+
+        // both ends ratchet at the same point and stay in sync
+        enc.ratchet();
+        dec.ratchet();
+        let mut buffer = *b"efgh";
+        let tag = enc.encrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]));
+        assert_ne!(tag, tag_of(&mut unratcheted, b"efgh"));
+        dec.decrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]), &tag)
+            .unwrap();
+        assert_eq!(buffer, *b"efgh");
+
+        // ratcheting depends on the history
+        let mut a = init();
+        let mut b = init();
+        tag_of(&mut a, b"abcd");
+        tag_of(&mut b, b"abce");
+        a.ratchet();
+        b.ratchet();
+        assert_ne!(tag_of(&mut a, b""), tag_of(&mut b, b""));
+
+        // an automatic policy ratchets at the same point as a manual one
+        let mut auto = AutoRatchet::new(init(), RatchetPolicy::every_messages(2));
+        let mut manual = init();
+        for i in 0..5u8 {
+            let mut buffer = [i];
+            let tag = auto.encrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]));
+            assert_eq!(tag, tag_of(&mut manual, &[i]));
+            if i % 2 == 1 {
+                manual.ratchet();
+            }
+        }
+
+        let mut enc = AutoRatchet::new(init(), RatchetPolicy::every_bytes(10));
+        let mut dec = AutoRatchet::new(init(), RatchetPolicy::every_bytes(10));
+        for msg in [&b"abcdef"[..], b"ghijkl", b"m", b"", b"nopqrstuvwxyz"] {
+            let mut buffer = msg.to_vec();
+            enc.encrypt_in_place(b"ad", &mut buffer).unwrap();
+
+            // failed decryptions do not count towards the policy
+            buffer[0] ^= 1;
+            dec.decrypt_in_place(b"ad", &mut buffer).unwrap_err();
+            buffer[0] ^= 1;
+
+            dec.decrypt_in_place(b"ad", &mut buffer).unwrap();
+            assert_eq!(buffer, msg);
+        }
+    }
+
+    #[test]
+    fn check_channel() {
+        let key = [0; 32];
+        let iv = [0; 16];
+        let mut initiator = SessionChannel::<XoofffSaneCore>::init(&key, &iv, Role::Initiator);
+        let mut responder = SessionChannel::<XoofffSaneCore>::init(&key, &iv, Role::Responder);
+
+        // the two directions are independent of each other and of a plain session
+        let mut a = *b"abcd";
+        let mut b = *b"abcd";
+        let mut c = *b"abcd";
+        let tag_a = initiator
+            .sender()
+            .encrypt_inout_detached(b"", InOutBuf::from(&mut a[..]));
+        let tag_b = responder
+            .sender()
+            .encrypt_inout_detached(b"", InOutBuf::from(&mut b[..]));
+        let tag_c =
+            XoofffSane::init(&key, &iv).encrypt_inout_detached(b"", InOutBuf::from(&mut c[..]));
+        assert_ne!(a, b);
+        assert_ne!(tag_a, tag_b);
+        assert_ne!(a, c);
+        assert_ne!(tag_a, tag_c);
+
+        // nor of a plain session with the direction appended to the IV
+        let mut d = *b"abcd";
+        let tag_d = XoofffSane::init(&key, &[&iv[..], &[0x01]].concat())
+            .encrypt_inout_detached(b"", InOutBuf::from(&mut d[..]));
+        assert_ne!(a, d);
+        assert_ne!(tag_a, tag_d);
+
+        // messages cross over in both directions
+        responder
+            .receiver()
+            .decrypt_inout_detached(b"", InOutBuf::from(&mut a[..]), &tag_a)
+            .unwrap();
+        initiator
+            .receiver()
+            .decrypt_inout_detached(b"", InOutBuf::from(&mut b[..]), &tag_b)
+            .unwrap();
+        assert_eq!(a, *b"abcd");
+        assert_eq!(b, *b"abcd");
+
+        // the split halves can be used from separate threads
+        let (mut send, _) = initiator.split();
+        let (_, mut receive) = responder.split();
+        let sent = std::thread::spawn(move || {
+            (0..4u8)
+                .map(|i| {
+                    let mut buffer = [i; 8];
+                    let tag = send.encrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]));
+                    (buffer, tag)
+                })
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+        for (i, (mut buffer, tag)) in sent.into_iter().enumerate() {
+            receive
+                .decrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]), &tag)
+                .unwrap();
+            assert_eq!(buffer, [i as u8; 8]);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_io() {
+        use std::io::{Cursor, ErrorKind, Read, Write};
+
+        use crate::io::{SessionReader, SessionWriter};
+
+        let key = [0; 32];
+        let iv = [0; 16];
+        let data: Vec<u8> = (0..100).collect();
+
+        let mut writer = SessionWriter::with_frame_size(Vec::new(), XoofffSane::init(&key, &iv), 7);
+        for chunk in data.chunks(11) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush().unwrap();
+        let wire = writer.finish().unwrap();
+
+        let read_all = |wire: &[u8]| {
+            let mut reader = SessionReader::new(Cursor::new(wire), XoofffSane::init(&key, &iv));
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).map(|_| out)
+        };
+        assert_eq!(read_all(&wire).unwrap(), data);
+
+        // every truncation fails, including right after the last data frame
+        for len in 0..wire.len() {
+            let err = read_all(&wire[..len]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        }
+
+        // a modified frame fails, and the reader stays failed
+        let mut tampered = wire.clone();
+        tampered[5] ^= 1;
+        let mut reader =
+            SessionReader::new(Cursor::new(&tampered[..]), XoofffSane::init(&key, &iv));
+        let mut out = [0; 100];
+        let err = reader.read_exact(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        reader.read(&mut out).unwrap_err();
+
+        // frames larger than the reader accepts are rejected
+        let mut reader = SessionReader::with_max_frame_size(
+            Cursor::new(&wire[..]),
+            XoofffSane::init(&key, &iv),
+            6,
+        );
+        reader.read(&mut out).unwrap_err();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_file() {
+        use std::format;
+        use std::io::{Cursor, Read, Write};
+
+        use crate::file::{FileReader, FileWriter, Header, open_file, seal_file};
+        use crate::instances::XoofffSane as XoofffFile;
+
+        let key = [3; 32];
+        let iv = [4; 16];
+        let seal = |data: &[u8]| {
+            let mut writer =
+                FileWriter::<_, XoofffFile>::with_chunk_size(Vec::new(), &key, b"id", &iv, 16)
+                    .unwrap();
+            writer.write_all(data).unwrap();
+            writer.finish().unwrap()
+        };
+        let open = |file: &[u8]| {
+            let mut reader = FileReader::<_, XoofffFile>::new(Cursor::new(file), &key)?;
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).map(|_| out)
+        };
+
+        for len in [0, 1, 15, 16, 17, 48, 100] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let file = seal(&data);
+            assert_eq!(open(&file).unwrap(), data);
+
+            // every truncation fails, including at chunk boundaries
+            for cut in 0..file.len() {
+                open(&file[..cut]).unwrap_err();
+            }
+            // trailing data fails
+            let mut extended = file.clone();
+            extended.push(0);
+            open(&extended).unwrap_err();
+        }
+
+        // data after the last chunk fails, even when the reader reports the end before it
+        struct EndThenMore<'a>(&'a [u8], Option<&'a [u8]>);
+        impl Read for EndThenMore<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.0.is_empty() {
+                    if let Some(rest) = self.1.take() {
+                        self.0 = rest;
+                    }
+                    return Ok(0);
+                }
+                self.0.read(buf)
+            }
+        }
+        let file = seal(&[1; 20]);
+        let open_split = |trailing: &[u8]| {
+            let mut reader =
+                FileReader::<_, XoofffFile>::new(EndThenMore(&file, Some(trailing)), &key)?;
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).map(|_| out)
+        };
+        assert_eq!(open_split(&[]).unwrap(), [1; 20]);
+        open_split(&[0]).unwrap_err();
+        open_split(&file).unwrap_err();
+
+        let data = [7; 40];
+        let file = seal(&data);
+        let header_len = Header::read_from(&file[..]).unwrap().encode().len();
+        assert_eq!(header_len, 11 + 2 + 16);
+
+        // the header is authenticated
+        let mut tampered = file.clone();
+        tampered[12] ^= 1;
+        open(&tampered).unwrap_err();
+        #[cfg(feature = "kravatte")]
+        assert!(FileReader::<_, crate::instances::KravatteSane>::new(&file[..], &key).is_err());
+
+        // swapped chunks fail
+        let record = 16 + 16;
+        let mut swapped = file.clone();
+        let (first, rest) = swapped[header_len..].split_at_mut(record);
+        first.swap_with_slice(&mut rest[..record]);
+        open(&swapped).unwrap_err();
+
+        // whole files, with the output only replaced once authenticated
+        let dir = std::env::temp_dir().join(format!("farfalle-check-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (plain, sealed, opened) = (dir.join("plain"), dir.join("sealed"), dir.join("opened"));
+        std::fs::write(&plain, vec![9; 200_000]).unwrap();
+        seal_file::<XoofffFile>(&key, b"", &iv, &plain, &sealed).unwrap();
+        open_file::<XoofffFile>(&key, &sealed, &opened).unwrap();
+        assert_eq!(std::fs::read(&opened).unwrap(), vec![9; 200_000]);
+
+        let mut bytes = std::fs::read(&sealed).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(&sealed, bytes).unwrap();
+        open_file::<XoofffFile>(&key, &sealed, &opened).unwrap_err();
+        assert_eq!(std::fs::read(&opened).unwrap(), vec![9; 200_000]);
+        assert!(!dir.join(".opened.partial").exists());
+        std::fs::remove_file(&opened).unwrap();
+        open_file::<XoofffFile>(&key, &sealed, &opened).unwrap_err();
+        assert!(!opened.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_seekable() {
+        use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+        use crate::seekable::{DeckSeekableCore, HEADER_LEN, SeekableReader, SeekableWriter};
+
+        struct XoofffSeekableCore;
+        impl DeckSeekableCore for XoofffSeekableCore {
+            type Core = XoofffDeckCore;
+            type TagSize = U16;
+        }
+        type Reader<'a> = SeekableReader<Cursor<&'a [u8]>, XoofffSeekableCore>;
+
+        let key = [5; 32];
+        let nonce = [6; 16];
+        let seal = |data: &[u8]| {
+            let mut writer = SeekableWriter::<_, XoofffSeekableCore>::with_segment_size(
+                Vec::new(),
+                &key,
+                &nonce,
+                16,
+            )
+            .unwrap();
+            writer.write_all(data).unwrap();
+            writer.finish().unwrap()
+        };
+
+        for len in [0, 1, 15, 16, 17, 100] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let (object, commitment) = seal(&data);
+            assert_eq!(object[object.len() - 16..], commitment[..]);
+
+            let mut reader = Reader::new(Cursor::new(&object[..]), &key).unwrap();
+            assert_eq!(reader.len(), len as u64);
+            reader.verify_commitment().unwrap();
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+
+            // dropping the last segment or cutting into it fails
+            for cut in HEADER_LEN..object.len() {
+                let Ok(mut reader) = Reader::new(Cursor::new(&object[..cut]), &key) else {
+                    continue;
+                };
+                let mut out = Vec::new();
+                assert!(
+                    reader.verify_commitment().is_err() || reader.read_to_end(&mut out).is_err()
+                );
+            }
+        }
+
+        // keeping whole segments and appending any bytes for an empty last segment fails at the end
+        let data: Vec<u8> = (0..100u8).collect();
+        let (object, _) = seal(&data);
+        for segments in 0..=6 {
+            let mut truncated = object[..HEADER_LEN + segments * (16 + 16)].to_vec();
+            truncated.extend_from_slice(&[0; 2 * 16]);
+            let mut reader = Reader::new(Cursor::new(&truncated[..]), &key).unwrap();
+            assert_eq!(reader.len(), segments as u64 * 16);
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap_err();
+        }
+
+        // random access
+        let data: Vec<u8> = (0..200u8).collect();
+        let (object, _) = seal(&data);
+        let mut reader = Reader::new(Cursor::new(&object[..]), &key).unwrap();
+        for (start, len) in [(0, 5), (14, 4), (100, 50), (190, 10), (32, 16)] {
+            let mut out = vec![0; len];
+            reader.seek(SeekFrom::Start(start as u64)).unwrap();
+            reader.read_exact(&mut out).unwrap();
+            assert_eq!(out, data[start..start + len]);
+        }
+        assert_eq!(reader.seek(SeekFrom::End(-1)).unwrap(), 199);
+        reader.seek(SeekFrom::Current(-500)).unwrap_err();
+
+        // a modified segment only fails the reads that touch it
+        let mut tampered = object.clone();
+        tampered[HEADER_LEN + 32 + 3] ^= 1;
+        let mut reader = Reader::new(Cursor::new(&tampered[..]), &key).unwrap();
+        let mut out = [0; 16];
+        reader.seek(SeekFrom::Start(16)).unwrap();
+        reader.read_exact(&mut out).unwrap_err();
+        reader.seek(SeekFrom::Start(32)).unwrap();
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(out[..], data[32..48]);
+        reader.verify_commitment().unwrap();
+
+        // swapped segments fail, as does the commitment
+        let record = 16 + 16;
+        let mut swapped = object.clone();
+        let (first, rest) = swapped[HEADER_LEN..].split_at_mut(record);
+        first.swap_with_slice(&mut rest[..record]);
+        let mut reader = Reader::new(Cursor::new(&swapped[..]), &key).unwrap();
+        reader.read_exact(&mut out).unwrap_err();
+        reader.verify_commitment().unwrap_err();
+    }
+
+    #[cfg(feature = "codec")]
+    #[tokio::test]
+    async fn check_codec() {
+        use std::io::Write;
+
+        use bytes::BytesMut;
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::{Decoder, FramedRead, FramedWrite};
+
+        use crate::codec::SessionCodec;
+        use crate::io::SessionWriter;
+
+        let key = [0; 32];
+        let iv = [0; 16];
+        let messages: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 7 * i as usize]).collect();
+
+        // a small duplex buffer forces frames to arrive in pieces
+        let (client, server) = tokio::io::duplex(16);
+        let codec = SessionCodec::with_max_frame_size(XoofffSane::init(&key, &iv), 200);
+        let mut sink = FramedWrite::new(client, codec);
+        let to_send = messages.clone();
+        let writer = tokio::spawn(async move {
+            for msg in &to_send {
+                sink.send(&msg[..]).await.unwrap();
+            }
+        });
+
+        let codec = SessionCodec::with_max_frame_size(XoofffSane::init(&key, &iv), 200);
+        let mut stream = FramedRead::new(server, codec);
+        for msg in &messages {
+            let received = stream.next().await.unwrap().unwrap();
+            assert_eq!(&received[..], &msg[..]);
+        }
+        writer.await.unwrap();
+
+        // frames over the maximum size and modified frames are rejected
+        let mut wire = BytesMut::new();
+        let mut enc = SessionCodec::new(XoofffSane::init(&key, &iv));
+        tokio_util::codec::Encoder::encode(&mut enc, &b"hello"[..], &mut wire).unwrap();
+
+        let mut dec = SessionCodec::with_max_frame_size(XoofffSane::init(&key, &iv), 4);
+        dec.decode(&mut wire.clone()).unwrap_err();
+
+        let mut dec = SessionCodec::new(XoofffSane::init(&key, &iv));
+        let mut tampered = wire.clone();
+        tampered[4] ^= 1;
+        dec.decode(&mut tampered).unwrap_err();
+        dec.decode(&mut wire.clone()).unwrap_err();
+
+        // the header of the last frame of an `io` stream is rejected
+        let mut dec = SessionCodec::new(XoofffSane::init(&key, &iv));
+        let mut flagged = wire.clone();
+        flagged[3] |= 0x80;
+        dec.decode(&mut flagged).unwrap_err();
+
+        // a message is framed like a flushed frame of `io`
+        let mut writer = SessionWriter::new(Vec::new(), XoofffSane::init(&key, &iv));
+        writer.write_all(b"hello").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref()[..], wire[..]);
+
+        let mut dec = SessionCodec::new(XoofffSane::init(&key, &iv));
+        let mut partial = BytesMut::from(&wire[..wire.len() - 1]);
+        assert!(dec.decode(&mut partial).unwrap().is_none());
+        partial.extend_from_slice(&wire[wire.len() - 1..]);
+        assert_eq!(&dec.decode(&mut partial).unwrap().unwrap()[..], b"hello");
+    }
+
+    struct XoofffDatagramCore;
+    impl DeckDatagramCore for XoofffDatagramCore {
+        type Core = XoofffDeckCore;
+        type TagSize = U16;
+    }
+
+    #[test]
+    fn check_datagram() {
+        let key = [0; 32];
+        let mut sender = DatagramSender::<XoofffDatagramCore>::init(&key);
+        let mut receiver = DatagramReceiver::<XoofffDatagramCore>::init(&key);
+        let overhead = DatagramSender::<XoofffDatagramCore>::overhead();
+
+        let packets: Vec<Vec<u8>> = (0..5u8)
+            .map(|i| {
+                let payload = [i; 10];
+                let mut packet = vec![0; payload.len() + overhead];
+                let len = sender.seal(b"ad", &payload, &mut packet).unwrap();
+                assert_eq!(len, packet.len());
+                packet
+            })
+            .collect();
+
+        // each packet has its own keystream
+        let keystream =
+            |i: usize| -> Vec<u8> { packets[i][8..18].iter().map(|b| b ^ i as u8).collect() };
+        assert_ne!(keystream(0), keystream(1));
+
+        // out of order delivery is accepted, replays are not
+        for i in [1, 0, 4, 2] {
+            let mut packet = packets[i].clone();
+            let (seq, payload) = receiver.open(b"ad", &mut packet).unwrap();
+            assert_eq!(seq, i as u64);
+            assert_eq!(*payload, [i as u8; 10]);
+
+            let mut packet = packets[i].clone();
+            receiver.open(b"ad", &mut packet).unwrap_err();
+        }
+
+        // the sequence number and associated data are authenticated
+        let mut packet = packets[3].clone();
+        packet[0] ^= 1;
+        receiver.open(b"ad", &mut packet).unwrap_err();
+        let mut packet = packets[3].clone();
+        receiver.open(b"da", &mut packet).unwrap_err();
+        assert_eq!(packet, packets[3]);
+        let mut packet = packets[3].clone();
+        receiver.open(b"ad", &mut packet).unwrap();
+
+        // too short to hold a sequence number and tag
+        receiver.open(b"ad", &mut [0; 23]).unwrap_err();
+
+        // a resumed sender continues the sequence instead of reusing it
+        assert_eq!(sender.next_seq(), 5);
+        let mut resumed = DatagramSender::<XoofffDatagramCore>::resume(&key, sender.next_seq());
+        let mut packet = vec![0; 10 + overhead];
+        resumed.seal(b"ad", &[5; 10], &mut packet).unwrap();
+        let mut expected = vec![0; 10 + overhead];
+        sender.seal(b"ad", &[5; 10], &mut expected).unwrap();
+        assert_eq!(packet, expected);
+        let (seq, payload) = receiver.open(b"ad", &mut packet).unwrap();
+        assert_eq!((seq, &*payload), (5, &[5; 10][..]));
+
+        // the last sequence number is never used
+        let mut resumed = DatagramSender::<XoofffDatagramCore>::resume(&key, u64::MAX);
+        resumed.seal(b"ad", &[], &mut packet).unwrap_err();
+    }
+
+    #[test]
+    fn check_replay_window() {
+        let mut window = ReplayWindow::default();
+        assert!(window.check(0));
+        window.update(0).unwrap();
+        assert!(!window.check(0));
+
+        window.update(WINDOW_SIZE).unwrap();
+        assert!(!window.check(0));
+        assert!(window.check(1));
+        window.update(1).unwrap();
+        assert!(!window.check(1));
+        assert!(window.check(2));
+
+        // jumping far ahead forgets the whole window
+        window.update(1000).unwrap();
+        for seq in 1000 - WINDOW_SIZE + 1..1000 {
+            assert!(window.check(seq));
+        }
+        assert!(!window.check(1000 - WINDOW_SIZE));
+        assert!(!window.check(1000));
+        assert!(window.check(1001));
+
+        // rejected sequence numbers leave the window untouched
+        let before = window;
+        for seq in [1000, 1000 - WINDOW_SIZE, 0, u64::MAX] {
+            window.update(seq).unwrap_err();
+            assert_eq!(window, before);
+        }
+        window.update(u64::MAX - 1).unwrap();
+        assert!(!window.check(u64::MAX));
+    }
+
     struct XoofffSanseCore;
     impl DeckSanseCore for XoofffSanseCore {
         type Core = XoofffDeckCore;
         type TagSize = U16;
     }
 
+    impl AlgorithmName for XoofffSanseCore {
+        fn write_alg_name(f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("Xoofff-SANSE")
+        }
+    }
+
     type XoofffSanse = DeckSanse<XoofffSanseCore>;
 
+    /// Run the same messages through an uninterrupted session and a resumed one.
+    #[cfg(feature = "alloc")]
+    fn check_resumed<S: SessionAead>(
+        enc: &mut S,
+        dec: &mut S,
+        enc_resumed: &mut S,
+        dec_resumed: &mut S,
+    ) {
+        let msg = [0x5a; 100];
+        for ad in [&b"foo"[..], b"", b"bar"] {
+            for len in [0, 1, 47, 48, 100] {
+                let mut a = msg[..len].to_vec();
+                let mut b = msg[..len].to_vec();
+                let tag_a = enc.encrypt_inout_detached(ad, InOutBuf::from(&mut a[..]));
+                let tag_b = enc_resumed.encrypt_inout_detached(ad, InOutBuf::from(&mut b[..]));
+                assert_eq!(tag_a, tag_b);
+                assert_eq!(a, b);
+
+                dec.decrypt_inout_detached(ad, InOutBuf::from(&mut a[..]), &tag_a)
+                    .unwrap();
+                dec_resumed
+                    .decrypt_inout_detached(ad, InOutBuf::from(&mut b[..]), &tag_b)
+                    .unwrap();
+                assert_eq!(a, msg[..len]);
+                assert_eq!(b, msg[..len]);
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn check_snapshot() {
+        use crate::snapshot::SnapshotError;
+
+        let key = [0; 32];
+        let iv = [0; 16];
+        let wrapping_key = [1; 32];
+
+        let mut enc = XoofffSane::init(&key, &iv);
+        let mut dec = XoofffSane::init(&key, &iv);
+        let mut msg = *b"abcd";
+        let tag = enc.encrypt_inout_detached(b"foo", InOutBuf::from(&mut msg[..]));
+        dec.decrypt_inout_detached(b"foo", InOutBuf::from(&mut msg[..]), &tag)
+            .unwrap();
+
+        let snapshot = enc.export();
+        let sealed = dec.export_sealed(&wrapping_key);
+        let mut enc_resumed = XoofffSane::import(&snapshot).unwrap();
+        let mut dec_resumed = XoofffSane::import_sealed(&wrapping_key, &sealed).unwrap();
+        check_resumed(&mut enc, &mut dec, &mut enc_resumed, &mut dec_resumed);
+
+        assert_eq!(
+            XoofffSane::import_sealed(&[2; 32], &sealed).err(),
+            Some(SnapshotError::Unauthentic)
+        );
+        assert_eq!(
+            XoofffSanse::import(&snapshot).err(),
+            Some(SnapshotError::AlgorithmMismatch)
+        );
+        assert_eq!(
+            XoofffSane::import(&snapshot[..snapshot.len() - 1]).err(),
+            Some(SnapshotError::Malformed)
+        );
+
+        let mut enc = XoofffSanse::init(&key);
+        let mut dec = XoofffSanse::init(&key);
+        let mut msg = *b"abcd";
+        let tag = enc.encrypt_inout_detached(b"foo", InOutBuf::from(&mut msg[..]));
+        dec.decrypt_inout_detached(b"foo", InOutBuf::from(&mut msg[..]), &tag)
+            .unwrap();
+
+        let snapshot = enc.export();
+        let sealed = dec.export_sealed(&wrapping_key);
+        let mut enc_resumed = XoofffSanse::import(&snapshot).unwrap();
+        let mut dec_resumed = XoofffSanse::import_sealed(&wrapping_key, &sealed).unwrap();
+        check_resumed(&mut enc, &mut dec, &mut enc_resumed, &mut dec_resumed);
+
+        assert_eq!(
+            XoofffSane::import(&snapshot).err(),
+            Some(SnapshotError::AlgorithmMismatch)
+        );
+    }
+
     #[test]
     fn check_siv() {
         let key = [0; 32];
@@ -124,234 +1149,919 @@ mod tests {
         assert_eq!(msg2, *b"xyzw");
     }
 
-    #[derive(Default, Clone, Copy)]
-    struct Xoodoo<const R: usize> {}
-
-    impl<const R: usize> Permutation for Xoodoo<R> {
-        type Size = U48;
-
-        fn permute(block: &mut hybrid_array::Array<u8, Self::Size>) {
-            let mut b: [u32; 12] = unsafe { core::mem::transmute(block.clone()) };
-            xoodoo::<R>(&mut b);
-            *block = unsafe { core::mem::transmute(b) };
-        }
-    }
-
-    #[derive(Default, Clone, Copy)]
-    struct RollXC {}
-    impl Permutation for RollXC {
-        type Size = U48;
-
-        fn permute(block: &mut hybrid_array::Array<u8, Self::Size>) {
-            let mut b: [u32; 12] = unsafe { core::mem::transmute(block.clone()) };
-            rollxc(&mut b);
-            *block = unsafe { core::mem::transmute(b) };
-        }
-    }
-
-    #[derive(Default, Clone, Copy)]
-    struct RollXE {}
-    impl Permutation for RollXE {
-        type Size = U48;
-
-        fn permute(block: &mut hybrid_array::Array<u8, Self::Size>) {
-            let mut b: [u32; 12] = unsafe { core::mem::transmute(block.clone()) };
-            rollxe(&mut b);
-            *block = unsafe { core::mem::transmute(b) };
-        }
-    }
-
-    /// <https://docs.rs/xoodoo-p/0.1.0/src/xoodoo_p/lib.rs.html>
-    const MAX_ROUNDS: usize = 12;
-    fn xoodoo<const R: usize>(st: &mut [u32; 12]) {
-        debug_assert!(R <= MAX_ROUNDS, "R must be <= {} (was {}", R, MAX_ROUNDS);
-
-        // Load lanes into registers.
-        let mut a00 = st[0];
-        let mut a01 = st[1];
-        let mut a02 = st[2];
-        let mut a03 = st[3];
-        let mut a10 = st[4];
-        let mut a11 = st[5];
-        let mut a12 = st[6];
-        let mut a13 = st[7];
-        let mut a20 = st[8];
-        let mut a21 = st[9];
-        let mut a22 = st[10];
-        let mut a23 = st[11];
-
-        // Perform last R rounds.
-        for &round_key in &ROUND_KEYS[MAX_ROUNDS - R..MAX_ROUNDS] {
-            let p0 = a00 ^ a10 ^ a20;
-            let p1 = a01 ^ a11 ^ a21;
-            let p2 = a02 ^ a12 ^ a22;
-            let p3 = a03 ^ a13 ^ a23;
-
-            let e0 = p3.rotate_left(5) ^ p3.rotate_left(14);
-            let e1 = p0.rotate_left(5) ^ p0.rotate_left(14);
-            let e2 = p1.rotate_left(5) ^ p1.rotate_left(14);
-            let e3 = p2.rotate_left(5) ^ p2.rotate_left(14);
-
-            let tmp0 = e0 ^ a00 ^ round_key;
-            let tmp1 = e1 ^ a01;
-            let tmp2 = e2 ^ a02;
-            let tmp3 = e3 ^ a03;
-            let tmp4 = e3 ^ a13;
-            let tmp5 = e0 ^ a10;
-            let tmp6 = e1 ^ a11;
-            let tmp7 = e2 ^ a12;
-            let tmp8 = (e0 ^ a20).rotate_left(11);
-            let tmp9 = (e1 ^ a21).rotate_left(11);
-            let tmp10 = (e2 ^ a22).rotate_left(11);
-            let tmp11 = (e3 ^ a23).rotate_left(11);
-
-            a00 = (!tmp4 & tmp8) ^ tmp0;
-            a01 = (!tmp5 & tmp9) ^ tmp1;
-            a02 = (!tmp6 & tmp10) ^ tmp2;
-            a03 = (!tmp7 & tmp11) ^ tmp3;
-
-            a10 = ((!tmp8 & tmp0) ^ tmp4).rotate_left(1);
-            a11 = ((!tmp9 & tmp1) ^ tmp5).rotate_left(1);
-            a12 = ((!tmp10 & tmp2) ^ tmp6).rotate_left(1);
-            a13 = ((!tmp11 & tmp3) ^ tmp7).rotate_left(1);
-
-            a20 = ((!tmp2 & tmp6) ^ tmp10).rotate_left(8);
-            a21 = ((!tmp3 & tmp7) ^ tmp11).rotate_left(8);
-            a22 = ((!tmp0 & tmp4) ^ tmp8).rotate_left(8);
-            a23 = ((!tmp1 & tmp5) ^ tmp9).rotate_left(8);
-        }
-
-        // Load registers into lanes.
-        st[0] = a00;
-        st[1] = a01;
-        st[2] = a02;
-        st[3] = a03;
-        st[4] = a10;
-        st[5] = a11;
-        st[6] = a12;
-        st[7] = a13;
-        st[8] = a20;
-        st[9] = a21;
-        st[10] = a22;
-        st[11] = a23;
-    }
-
-    const ROUND_KEYS: [u32; MAX_ROUNDS] = [
-        0x00000058, 0x00000038, 0x000003C0, 0x000000D0, 0x00000120, 0x00000014, 0x00000060,
-        0x0000002C, 0x00000380, 0x000000F0, 0x000001A0, 0x00000012,
-    ];
-
-    fn rollxc(st: &mut [u32; 12]) {
-        // Load lanes into registers.
-        let mut a00 = st[0];
-        let mut a01 = st[1];
-        let mut a02 = st[2];
-        let mut a03 = st[3];
-        let mut a10 = st[4];
-        let mut a11 = st[5];
-        let mut a12 = st[6];
-        let mut a13 = st[7];
-        let mut a20 = st[8];
-        let mut a21 = st[9];
-        let mut a22 = st[10];
-        let mut a23 = st[11];
-
-        // A00 <- A00 + (A00 << 13) + (A10 <<< 3)
-        a00 = a00 ^ (a00 << 13) ^ a10.rotate_left(3);
-
-        // B <- A0 <<< (3, 0)
-        let b0 = a03;
-        let b1 = a00;
-        let b2 = a01;
-        let b3 = a02;
-
-        // A0 <- A1
-        a00 = a10;
-        a01 = a11;
-        a02 = a12;
-        a03 = a13;
-
-        // A1 <- A2
-        a10 = a20;
-        a11 = a21;
-        a12 = a22;
-        a13 = a23;
-
-        // A2 <- B
-        a20 = b0;
-        a21 = b1;
-        a22 = b2;
-        a23 = b3;
-
-        // Load registers into lanes.
-        st[0] = a00;
-        st[1] = a01;
-        st[2] = a02;
-        st[3] = a03;
-        st[4] = a10;
-        st[5] = a11;
-        st[6] = a12;
-        st[7] = a13;
-        st[8] = a20;
-        st[9] = a21;
-        st[10] = a22;
-        st[11] = a23;
-    }
-
-    fn rollxe(st: &mut [u32; 12]) {
-        // Load lanes into registers.
-        let mut a00 = st[0];
-        let mut a01 = st[1];
-        let mut a02 = st[2];
-        let mut a03 = st[3];
-        let mut a10 = st[4];
-        let mut a11 = st[5];
-        let mut a12 = st[6];
-        let mut a13 = st[7];
-        let mut a20 = st[8];
-        let mut a21 = st[9];
-        let mut a22 = st[10];
-        let mut a23 = st[11];
-
-        // A00 <- A10 . A20 + (A00 <<< 5) + (A10 <<< 13) + 0x00000007
-        a00 = a10 & a20 ^ (a00.rotate_left(5)) ^ a10.rotate_left(13) ^ 0x00000007;
-
-        // B <- A0 <<< (3, 0)
-        let b0 = a03;
-        let b1 = a00;
-        let b2 = a01;
-        let b3 = a02;
-
-        // A0 <- A1
-        a00 = a10;
-        a01 = a11;
-        a02 = a12;
-        a03 = a13;
-
-        // A1 <- A2
-        a10 = a20;
-        a11 = a21;
-        a12 = a22;
-        a13 = a23;
-
-        // A2 <- B
-        a20 = b0;
-        a21 = b1;
-        a22 = b2;
-        a23 = b3;
-
-        // Load registers into lanes.
-        st[0] = a00;
-        st[1] = a01;
-        st[2] = a02;
-        st[3] = a03;
-        st[4] = a10;
-        st[5] = a11;
-        st[6] = a12;
-        st[7] = a13;
-        st[8] = a20;
-        st[9] = a21;
-        st[10] = a22;
-        st[11] = a23;
+    struct XoofffWbcCore;
+    impl WideBlockCipherCore for XoofffWbcCore {
+        type BlockSize = U48;
+        type G = XoofffDeckCore;
+        type H = XoofffDeckCore;
+        type Alignnemt = U8;
+    }
+
+    type XoofffWbc = WideBlockCipher<XoofffWbcCore>;
+    type XoofffWbcAe = WideBlockCipherAuthenticated<XoofffWbcCore, U16>;
+    type XoofffSector = SectorCipher<XoofffWbcCore>;
+
+    #[test]
+    fn check_wbc_split() {
+        use crate::wbc::split;
+
+        // up to two blocks, the parts differ by at most one alignment unit
+        for (n, s) in [(0, 0), (10, 8), (24, 16), (86, 40)] {
+            assert_eq!(split(n, 8, 48), s, "n = {n}");
+        }
+        // beyond, the left part is (q - 2^x) blocks less the alignment, where
+        // q = ceil((n + l + 1) / b) and 2^x is the largest power of two below q
+        for (n, s) in [(87, 40), (100, 40), (200, 40), (300, 136), (1000, 280)] {
+            assert_eq!(split(n, 8, 48), s, "n = {n}");
+        }
+    }
+
+    #[test]
+    fn check_wbc_ae() {
+        let key = [0; 32];
+
+        let mut buffer = b"hello world".to_vec();
+        XoofffWbcAe::init(&key)
+            .encrypt_in_place(b"tweak", &mut buffer)
+            .unwrap();
+        assert_eq!(buffer.len(), 11 + 16);
+
+        let mut tampered = buffer.clone();
+        tampered[0] ^= 1;
+        XoofffWbcAe::init(&key)
+            .decrypt_in_place(b"tweak", &mut tampered)
+            .unwrap_err();
+        tampered[0] ^= 1;
+        assert_eq!(tampered, buffer);
+
+        let pt = XoofffWbcAe::init(&key)
+            .decrypt_in_place(b"tweak", &mut buffer)
+            .unwrap();
+        assert_eq!(pt, b"hello world");
+    }
+
+    #[test]
+    fn check_runtime_params() {
+        type RuntimeSane = RuntimeDeckSane<XoofffDeckCore>;
+        type RuntimeSanse = RuntimeDeckSanse<XoofffDeckCore>;
+        type RuntimeWbcAe = RuntimeWideBlockCipherAuthenticated<XoofffWbcCore>;
+
+        let key = [0; 32];
+        let iv = [0; 16];
+
+        // tags shorter than the minimum or longer than the maximum are rejected
+        for tag_size in [0, 4, MIN_TAG_SIZE - 1, MAX_TAG_SIZE + 1] {
+            assert_eq!(
+                RuntimeSane::init(&key, &iv, tag_size, 8).err(),
+                Some(InvalidParameters)
+            );
+            assert_eq!(
+                RuntimeSanse::init(&key, tag_size).err(),
+                Some(InvalidParameters)
+            );
+            assert!(RuntimeWbcAe::init(&key, 8, tag_size).is_err());
+        }
+        // alignments must be positive and bounded
+        for alignment in [0, MAX_ALIGNMENT + 1, usize::MAX] {
+            assert_eq!(
+                RuntimeSane::init(&key, &iv, 16, alignment).err(),
+                Some(InvalidParameters)
+            );
+        }
+        assert!(RuntimeSane::init(&key, &iv, 16, MAX_ALIGNMENT).is_ok());
+        // and for the wide block cipher, divide the block size
+        for alignment in [0, 1, 5, 96, usize::MAX] {
+            assert!(XoofffWbc::with_alignment(&key, alignment).is_err());
+            assert!(RuntimeWbcAe::init(&key, alignment, 16).is_err());
+        }
+
+        // the runtime versions match the typed versions with the same parameters
+        let mut typed_sane = XoofffSane::init(&key, &iv);
+        let mut sane = RuntimeSane::init(&key, &iv, 16, 8).unwrap();
+        let mut typed_sanse = XoofffSanse::init(&key);
+        let mut sanse = RuntimeSanse::init(&key, 16).unwrap();
+        for msg in [&b""[..], b"abcd", &[7; 100]] {
+            let mut expected = msg.to_vec();
+            typed_sane.encrypt_in_place(b"ad", &mut expected).unwrap();
+            let mut buffer = msg.to_vec();
+            sane.encrypt_in_place(b"ad", &mut buffer).unwrap();
+            assert_eq!(buffer, expected);
+
+            let mut expected = msg.to_vec();
+            typed_sanse.encrypt_in_place(b"ad", &mut expected).unwrap();
+            let mut buffer = msg.to_vec();
+            sanse.encrypt_in_place(b"ad", &mut buffer).unwrap();
+            assert_eq!(buffer, expected);
+        }
+
+        let mut expected = b"hello world".to_vec();
+        XoofffWbcAe::init(&key)
+            .encrypt_in_place(b"tweak", &mut expected)
+            .unwrap();
+        let mut buffer = b"hello world".to_vec();
+        RuntimeWbcAe::init(&key, 8, 16)
+            .unwrap()
+            .encrypt_in_place(b"tweak", &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, expected);
+
+        // the tag is rounded up to the alignment, also when neither divides the other
+        let mut keystream = [0; 32 + 64];
+        let mut deck = CoreWrapper::<XoofffDeckCore>::init(&key);
+        deck.update(&iv);
+        deck.finalize_deck().read(&mut keystream);
+        for (tag_size, alignment, offset) in [(12, 8, 16), (24, 16, 32), (16, 12, 24), (8, 1, 8)] {
+            let mut buffer = [0; 64];
+            RuntimeSane::init(&key, &iv, tag_size, alignment)
+                .unwrap()
+                .encrypt_inout_detached(
+                    b"",
+                    InOutBuf::from(&mut buffer[..]),
+                    &mut [0; 32][..tag_size],
+                );
+            assert_eq!(buffer[..], keystream[offset..offset + 64]);
+        }
+
+        // negotiated tag sizes round trip and reject forgeries
+        for tag_size in [8, 16, 32] {
+            let mut enc = RuntimeSane::init(&key, &iv, tag_size, 8).unwrap();
+            let mut dec = RuntimeSane::init(&key, &iv, tag_size, 8).unwrap();
+            let mut buffer = b"abcd".to_vec();
+            enc.encrypt_in_place(b"ad", &mut buffer).unwrap();
+            assert_eq!(buffer.len(), 4 + tag_size);
+            buffer[0] ^= 1;
+            dec.decrypt_in_place(b"ad", &mut buffer).unwrap_err();
+            buffer[0] ^= 1;
+            dec.decrypt_in_place(b"ad", &mut buffer).unwrap();
+            assert_eq!(buffer, b"abcd");
+
+            let mut enc = RuntimeSanse::init(&key, tag_size).unwrap();
+            let mut dec = RuntimeSanse::init(&key, tag_size).unwrap();
+            let mut buffer = b"abcd".to_vec();
+            let mut tag = [0; 32];
+            enc.encrypt_inout_detached(
+                b"ad",
+                InOutBuf::from(&mut buffer[..]),
+                &mut tag[..tag_size],
+            );
+            dec.decrypt_inout_detached(
+                b"ad",
+                InOutBuf::from(&mut buffer[..]),
+                &tag[..tag_size - 1],
+            )
+            .unwrap_err();
+            dec.decrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]), &tag[..tag_size])
+                .unwrap();
+            assert_eq!(buffer, b"abcd");
+        }
+    }
+
+    #[test]
+    fn check_wbc_ae_decrypt_to() {
+        let key = [0; 32];
+
+        let mut ciphertext = b"hello world".to_vec();
+        XoofffWbcAe::init(&key)
+            .encrypt_in_place(b"tweak", &mut ciphertext)
+            .unwrap();
+
+        let mut scratch = [0xff; 27];
+        let mut out = [0xaa; 11];
+        ciphertext[3] ^= 1;
+        XoofffWbcAe::init(&key)
+            .decrypt_to(b"tweak", &ciphertext, &mut scratch, &mut out)
+            .unwrap_err();
+        assert_eq!(out, [0xaa; 11]);
+        assert_eq!(scratch, [0; 27]);
+
+        ciphertext[3] ^= 1;
+        XoofffWbcAe::init(&key)
+            .decrypt_to(b"tweak", &ciphertext, &mut scratch, &mut out)
+            .unwrap();
+        assert_eq!(out, *b"hello world");
+        assert_eq!(scratch, [0; 27]);
+
+        // mismatched buffer lengths
+        XoofffWbcAe::init(&key)
+            .decrypt_to(b"tweak", &ciphertext, &mut scratch[1..], &mut out)
+            .unwrap_err();
+        XoofffWbcAe::init(&key)
+            .decrypt_to(b"tweak", &ciphertext, &mut scratch, &mut out[1..])
+            .unwrap_err();
+    }
+
+    #[test]
+    fn check_wbc_redundancy() {
+        let key = [0; 32];
+
+        // too few zero bytes to authenticate anything
+        for expansion in [0, 4, MIN_TAG_SIZE - 1] {
+            assert_eq!(ZeroExpansion::new(expansion).err(), Some(InvalidParameters));
+        }
+
+        for expansion in [MIN_TAG_SIZE, 32, 100] {
+            let redundancy = ZeroExpansion::new(expansion).unwrap();
+            let mut buffer = b"hello world".to_vec();
+            XoofffWbc::init(&key)
+                .encrypt_in_place_redundant(b"", &mut buffer, &redundancy)
+                .unwrap();
+            assert_eq!(buffer.len(), 11 + expansion);
+            let pt = XoofffWbc::init(&key)
+                .decrypt_in_place_redundant(b"", &mut buffer, &redundancy)
+                .unwrap();
+            assert_eq!(pt, b"hello world");
+        }
+
+        // a known header provides all the redundancy, with no expansion
+        let header = Verified {
+            expansion: 0,
+            verify: |pt: &[u8]| pt.get(..8).unwrap_or(&[]).ct_eq(b"FARFALLE".as_slice()),
+        };
+
+        let mut buffer = b"FARFALLE payload".to_vec();
+        XoofffWbc::init(&key)
+            .encrypt_in_place_redundant(b"", &mut buffer, &header)
+            .unwrap();
+        assert_eq!(buffer.len(), 16);
+
+        let mut tampered = buffer.clone();
+        tampered[15] ^= 1;
+        XoofffWbc::init(&key)
+            .decrypt_in_place_redundant(b"", &mut tampered, &header)
+            .unwrap_err();
+
+        let pt = XoofffWbc::init(&key)
+            .decrypt_in_place_redundant(b"", &mut buffer, &header)
+            .unwrap();
+        assert_eq!(pt, b"FARFALLE payload");
+
+        // plaintexts without the redundancy are rejected
+        let mut buffer = b"not the header!!".to_vec();
+        XoofffWbc::init(&key)
+            .encrypt_in_place_redundant(b"", &mut buffer, &header)
+            .unwrap();
+        XoofffWbc::init(&key)
+            .decrypt_in_place_redundant(b"", &mut buffer, &header)
+            .unwrap_err();
+    }
+
+    fn check_sectors(sector_size: usize) {
+        let key = [0; 32];
+        let cipher = XoofffSector::init(&key);
+
+        let plaintext: Vec<u8> = (0..4 * sector_size).map(|i| i as u8).collect();
+
+        let mut data = plaintext.clone();
+        cipher.encrypt_sectors(7, sector_size, InOutBuf::from(&mut data[..]));
+        assert_ne!(data, plaintext);
+
+        // each sector is encrypted independently under its own index
+        let mut single = plaintext[sector_size..2 * sector_size].to_vec();
+        cipher.encrypt_sector(8, InOutBuf::from(&mut single[..]));
+        assert_eq!(single, data[sector_size..2 * sector_size]);
+
+        // identical sectors at different indices encrypt differently
+        let mut a = vec![0; sector_size];
+        let mut b = vec![0; sector_size];
+        cipher.encrypt_sector(0, InOutBuf::from(&mut a[..]));
+        cipher.encrypt_sector(1, InOutBuf::from(&mut b[..]));
+        assert_ne!(a, b);
+
+        cipher.decrypt_sectors(7, sector_size, InOutBuf::from(&mut data[..]));
+        assert_eq!(data, plaintext);
+    }
+
+    #[test]
+    fn check_sectors_512() {
+        check_sectors(512);
+    }
+
+    #[test]
+    fn check_sectors_4096() {
+        check_sectors(4096);
+    }
+
+    type XoofffFpe = DeckFpe<XoofffDeckCore>;
+
+    #[test]
+    fn check_fpe_numerals() {
+        let key = [0; 32];
+        let fpe = XoofffFpe::init(&key);
+
+        let card = [4, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1];
+        let mut x = card;
+        fpe.encrypt_numerals(b"tweak", 10, &mut x).unwrap();
+        assert_ne!(x, card);
+        assert!(x.iter().all(|&d| d < 10));
+
+        let mut y = card;
+        fpe.encrypt_numerals(b"other", 10, &mut y).unwrap();
+        assert_ne!(x, y);
+
+        fpe.decrypt_numerals(b"tweak", 10, &mut x).unwrap();
+        assert_eq!(x, card);
+
+        // odd lengths and larger radixes
+        let word = [25, 0, 17, 3, 9, 11, 2];
+        let mut x = word;
+        fpe.encrypt_numerals(b"", 26, &mut x).unwrap();
+        assert!(x.iter().all(|&d| d < 26));
+        fpe.decrypt_numerals(b"", 26, &mut x).unwrap();
+        assert_eq!(x, word);
+
+        // domain too small, numeral out of range
+        assert_eq!(
+            fpe.encrypt_numerals(b"", 10, &mut [1, 2, 3]),
+            Err(InvalidDomain)
+        );
+        assert_eq!(
+            fpe.encrypt_numerals(b"", 10, &mut [1, 2, 3, 4, 5, 10]),
+            Err(InvalidDomain)
+        );
+    }
+
+    #[test]
+    fn check_fpe_numerals_distribution() {
+        let key = [0; 32];
+        let fpe = XoofffFpe::init(&key);
+
+        // encrypting 0..10000 as 6 digit strings should give uniformly distributed digits
+        let mut counts = [[0u32; 10]; 6];
+        let mut seen = std::collections::HashSet::new();
+        for i in 0..10000u32 {
+            let mut x = [0, 0, i / 1000 % 10, i / 100 % 10, i / 10 % 10, i % 10];
+            fpe.encrypt_numerals(b"", 10, &mut x).unwrap();
+            assert!(seen.insert(x));
+            for (count, &d) in counts.iter_mut().zip(&x) {
+                count[d as usize] += 1;
+            }
+        }
+        for count in counts.iter().flatten() {
+            assert!((850..1150).contains(count), "{counts:?}");
+        }
+    }
+
+    #[test]
+    fn check_fpe_int() {
+        let key = [0; 32];
+        let fpe = XoofffFpe::init(&key);
+
+        for max in [1, 2, 999, u32::MAX as u64, u64::MAX - 1, u64::MAX] {
+            for x in [0, 1, max / 3, max] {
+                let y = fpe.encrypt_int(b"tweak", max, x).unwrap();
+                assert!(y <= max);
+                assert_eq!(fpe.decrypt_int(b"tweak", max, y).unwrap(), x);
+            }
+        }
+
+        assert_eq!(fpe.encrypt_int(b"", 999, 1000), Err(InvalidDomain));
+    }
+
+    #[test]
+    fn check_fpe_int_distribution() {
+        let key = [0; 32];
+        let fpe = XoofffFpe::init(&key);
+
+        // a permutation of 0..=999 with few fixed points
+        let mut seen = [false; 1000];
+        let mut fixed = 0;
+        for x in 0..1000 {
+            let y = fpe.encrypt_int(b"", 999, x).unwrap();
+            assert!(!seen[y as usize]);
+            seen[y as usize] = true;
+            fixed += (y == x) as u32;
+        }
+        assert!(seen.iter().all(|&s| s));
+        assert!(fixed < 10);
+    }
+
+    fn check_instance_sane<D: DeckSaneCore>() {
+        let key = [7; 32];
+        let iv = [1; 16];
+        let mut enc = DeckSane::<D>::init(&key, &iv);
+        let mut dec = DeckSane::<D>::init(&key, &iv);
+
+        for len in [0, 1, 47, 48, 199, 200, 1000] {
+            let msg: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let mut buffer = msg.clone();
+            let tag = enc.encrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]));
+            if len > 0 {
+                assert_ne!(buffer, msg);
+            }
+
+            let mut bad_tag = tag.clone();
+            bad_tag[0] ^= 1;
+            let mut copy = buffer.clone();
+            dec.decrypt_inout_detached(b"ad", InOutBuf::from(&mut copy[..]), &bad_tag)
+                .unwrap_err();
+
+            dec.decrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]), &tag)
+                .unwrap();
+            assert_eq!(buffer, msg);
+        }
+    }
+
+    #[test]
+    fn check_instances() {
+        check_instance_sane::<XoofffSaneCore>();
+    }
+
+    #[test]
+    fn check_instance_permutations() {
+        use hybrid_array::Array;
+
+        use crate::Permutation;
+        use crate::instances::{RollXC, RollXE, Xoodoo};
+
+        let lanes = |b: &Array<u8, U48>| -> Vec<u32> {
+            b.chunks_exact(4)
+                .map(|lane| u32::from_le_bytes(lane.try_into().unwrap()))
+                .collect()
+        };
+
+        // zero rounds are the identity
+        let state = Array::<u8, U48>::from_fn(|i| i as u8);
+        let mut b = state.clone();
+        Xoodoo::<0>::permute(&mut b);
+        assert_eq!(b, state);
+        Xoodoo::<6>::permute(&mut b);
+        assert_ne!(b, state);
+
+        // the rolls shift the planes up and feed the new lane into the last plane, with A10 = 1
+        let mut b = Array::<u8, U48>::default();
+        b[16] = 1;
+        RollXC::permute(&mut b);
+        assert_eq!(lanes(&b), [1, 0, 0, 0, 0, 0, 0, 0, 0, 1 << 3, 0, 0]);
+
+        let mut b = Array::<u8, U48>::default();
+        b[16] = 1;
+        RollXE::permute(&mut b);
+        assert_eq!(lanes(&b), [1, 0, 0, 0, 0, 0, 0, 0, 0, 1 << 13 | 7, 0, 0]);
+    }
+
+    #[cfg(feature = "kravatte")]
+    #[test]
+    fn check_instances_kravatte() {
+        use crate::instances::{Kravatte, KravatteSane};
+
+        check_instance_sane::<KravatteSane>();
+
+        // the keyed instances differ
+        let mut a = [0; 32];
+        let mut b = [0; 32];
+        let mut xoofff = CoreWrapper::<Xoofff>::init(&[1; 16]);
+        xoofff.update(b"abc");
+        xoofff.finalize_deck().read(&mut a);
+        let mut kravatte = CoreWrapper::<Kravatte>::init(&[1; 16]);
+        kravatte.update(b"abc");
+        kravatte.finalize_deck().read(&mut b);
+        assert_ne!(a, b);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn check_dynamic() {
+        use crate::dynamic::{DynDeck, DynSessionAead, decks, find_deck, find_session, sessions};
+
+        let names: Vec<_> = decks().iter().map(|a| a.name().to_string()).collect();
+        let expected: &[&str] = &[
+            "Xoofff",
+            #[cfg(feature = "kravatte")]
+            "Kravatte",
+        ];
+        assert_eq!(names, expected);
+        let names: Vec<_> = sessions().iter().map(|a| a.name().to_string()).collect();
+        let expected: &[&str] = &[
+            "Xoofff-SANE",
+            "Xoofff-SANSE",
+            #[cfg(feature = "kravatte")]
+            "Kravatte-SANE",
+            #[cfg(feature = "kravatte")]
+            "Kravatte-SANSE",
+        ];
+        assert_eq!(names, expected);
+        assert!(find_deck("xoodoo").is_none());
+
+        // a boxed deck matches the typed one
+        let xoofff = find_deck("xoofff").unwrap();
+        assert_eq!(xoofff.state_size(), 48);
+        assert_eq!(xoofff.max_key_size(), 47);
+        assert!(xoofff.init(&[0; 48]).is_err());
+
+        let mut deck = xoofff.init(&[1; 16]).unwrap();
+        deck.dyn_update(b"abc");
+        let mut a = [0; 64];
+        deck.dyn_finalize_deck_into(&mut a);
+        // with both traits in scope, calls on the typed deck are not ambiguous
+        let mut typed = CoreWrapper::<Xoofff>::init(&[1; 16]);
+        typed.update(b"abc");
+        let mut b = [0; 64];
+        typed.finalize_deck().read(&mut b);
+        assert_eq!(a, b);
+        #[cfg(feature = "kravatte")]
+        assert_eq!(find_deck("KRAVATTE").unwrap().state_size(), 200);
+
+        // a boxed session matches the typed one
+        let sane = find_session("Xoofff-SANE").unwrap();
+        assert_eq!((sane.tag_size(), sane.max_key_size()), (16, 47));
+        let mut session = sane.init(&[2; 32], b"iv").unwrap();
+        let mut typed = DeckSane::<instances::XoofffSane>::init(&[2; 32], b"iv");
+        for msg in [&b""[..], b"hello", &[3; 100]] {
+            assert_eq!(
+                session.dyn_encrypt(b"ad", msg).unwrap(),
+                typed.encrypt(b"ad", msg).unwrap()
+            );
+        }
+
+        assert!(
+            find_session("xoofff-sanse")
+                .unwrap()
+                .init(&[2; 32], b"iv")
+                .is_err()
+        );
+
+        for algorithm in sessions() {
+            let iv: &[u8] = if algorithm.takes_iv() { b"iv" } else { b"" };
+            let mut enc = algorithm.init(&[4; 32], iv).unwrap();
+            let mut dec = algorithm.init(&[4; 32], iv).unwrap();
+            assert_eq!(enc.tag_size(), algorithm.tag_size());
+
+            for msg in [&b""[..], b"hello", &[5; 300]] {
+                let mut ciphertext = enc.dyn_encrypt(b"ad", msg).unwrap();
+                assert_eq!(ciphertext.len(), msg.len() + algorithm.tag_size());
+                ciphertext[0] ^= 1;
+                assert!(dec.dyn_decrypt(b"ad", &ciphertext).is_err());
+                ciphertext[0] ^= 1;
+                assert_eq!(dec.dyn_decrypt(b"ad", &ciphertext).unwrap(), msg);
+            }
+
+            let mut buffer = [0; 4];
+            let mut tag = vec![0; algorithm.tag_size()];
+            enc.dyn_encrypt_inout_detached(b"", InOutBuf::from(&mut buffer[..]), &mut tag);
+            assert!(
+                dec.dyn_decrypt_inout_detached(b"", InOutBuf::from(&mut buffer[..]), &tag[1..])
+                    .is_err()
+            );
+            dec.dyn_decrypt_inout_detached(b"", InOutBuf::from(&mut buffer[..]), &tag)
+                .unwrap();
+            assert_eq!(buffer, [0; 4]);
+        }
+    }
+
+    /// Deterministic pseudorandom bytes for the differential tests.
+    #[cfg(feature = "reference")]
+    fn pseudo_random(seed: usize, len: usize) -> Vec<u8> {
+        let mut x = (seed as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "reference")]
+    fn check_reference_deck<C: crate::farfalle::FarfalleCore>(max_len: usize) {
+        use crate::farfalle::Farfalle;
+        use crate::reference::{self, bits, bytes};
+
+        let key = pseudo_random(0, 16);
+        let f = reference::Farfalle::<C>::new(&key);
+        for len in 0..=max_len {
+            let first = pseudo_random(len, len);
+            let second = pseudo_random(len + 1, len / 2);
+
+            let mut deck = CoreWrapper::<Farfalle<C>>::init(&key);
+            deck.update(&first);
+            deck.finalize_deck();
+            deck.update(&second);
+            let mut out = vec![0; len + 3];
+            deck.finalize_deck().read(&mut out);
+
+            let expected = f.output(&[bits(&first), bits(&second)], 0, 8 * out.len());
+            assert_eq!(out, bytes(&expected), "length {len}");
+        }
+    }
+
+    #[cfg(feature = "reference")]
+    #[test]
+    fn check_reference_farfalle() {
+        check_reference_deck::<crate::instances::XoofffCore>(3 * 48 + 1);
+        #[cfg(feature = "kravatte")]
+        check_reference_deck::<crate::instances::KravatteCore>(2 * 200 + 1);
+    }
+
+    #[cfg(feature = "reference")]
+    #[test]
+    fn check_reference_sane() {
+        use crate::instances::XoofffCore;
+        use crate::reference::Sane;
+
+        let key = pseudo_random(1, 32);
+        let nonce = pseudo_random(2, 16);
+        let lengths: Vec<usize> = (0..=3 * 48 + 1).collect();
+
+        // a new session every few messages keeps the reference histories short
+        for lengths in lengths.chunks(16) {
+            let mut enc = DeckSane::<instances::XoofffSane>::init(&key, &nonce);
+            let mut dec = DeckSane::<instances::XoofffSane>::init(&key, &nonce);
+            let mut reference = Sane::<XoofffCore>::new(&key, &nonce, 16, 8);
+
+            for &len in lengths {
+                let ad = pseudo_random(len, len % 4 * 20);
+                let msg = pseudo_random(len + 1, len);
+
+                let mut buffer = msg.clone();
+                let tag = enc.encrypt_inout_detached(&ad, InOutBuf::from(&mut buffer[..]));
+                assert_eq!(
+                    reference.encrypt(&ad, &msg),
+                    (buffer.clone(), tag.to_vec()),
+                    "length {len}"
+                );
+
+                dec.decrypt_inout_detached(&ad, InOutBuf::from(&mut buffer[..]), &tag)
+                    .unwrap();
+                assert_eq!(buffer, msg);
+            }
+        }
+
+        // a tag and an alignment that do not divide each other
+        let mut runtime = RuntimeDeckSane::<Xoofff>::init(&key, &nonce, 12, 8).unwrap();
+        let mut reference = Sane::<XoofffCore>::new(&key, &nonce, 12, 8);
+        for len in [0, 1, 47, 48, 100] {
+            let msg = pseudo_random(len + 2, len);
+            let mut buffer = msg.clone();
+            let mut tag = [0; 12];
+            runtime.encrypt_inout_detached(b"ad", InOutBuf::from(&mut buffer[..]), &mut tag);
+            assert_eq!(
+                reference.encrypt(b"ad", &msg),
+                (buffer, tag.to_vec()),
+                "length {len}"
+            );
+        }
+    }
+
+    #[cfg(feature = "reference")]
+    #[test]
+    fn check_reference_sanse() {
+        use crate::instances::XoofffCore;
+        use crate::reference::Sanse;
+
+        let key = pseudo_random(3, 32);
+        let lengths: Vec<usize> = (0..=3 * 48 + 1).collect();
+
+        for lengths in lengths.chunks(16) {
+            let mut enc = DeckSanse::<instances::XoofffSanse>::init(&key);
+            let mut dec = DeckSanse::<instances::XoofffSanse>::init(&key);
+            let mut reference = Sanse::<XoofffCore>::new(&key, 32);
+            let mut reference_dec = Sanse::<XoofffCore>::new(&key, 32);
+
+            for &len in lengths {
+                let ad = pseudo_random(len, len % 4 * 20);
+                let msg = pseudo_random(len + 1, len);
+
+                let mut buffer = msg.clone();
+                let tag = enc.encrypt_inout_detached(&ad, InOutBuf::from(&mut buffer[..]));
+                assert_eq!(
+                    reference.encrypt(&ad, &msg),
+                    (buffer.clone(), tag.to_vec()),
+                    "length {len}"
+                );
+                assert_eq!(reference_dec.decrypt(&ad, &buffer, &tag), Some(msg.clone()));
+
+                dec.decrypt_inout_detached(&ad, InOutBuf::from(&mut buffer[..]), &tag)
+                    .unwrap();
+                assert_eq!(buffer, msg);
+            }
+        }
+    }
+
+    #[cfg(feature = "reference")]
+    #[test]
+    fn check_reference_wbc() {
+        use crate::instances::XoofffCore;
+        use crate::reference::Wbc;
+
+        let key = pseudo_random(4, 32);
+        let reference = Wbc::<XoofffCore, XoofffCore>::new(&key, 8);
+        let wbc = WideBlockCipher::<instances::XoofffWbc>::init(&key);
+
+        for len in 0..=4 * 48 + 1 {
+            let tweak = pseudo_random(len, len % 3 * 30);
+            let msg = pseudo_random(len + 1, len);
+
+            let mut buffer = msg.clone();
+            wbc.clone()
+                .encrypt_inout(&tweak, InOutBuf::from(&mut buffer[..]));
+            assert_eq!(reference.encrypt(&tweak, &msg), buffer, "length {len}");
+            assert_eq!(reference.decrypt(&tweak, &buffer), msg);
+
+            wbc.clone()
+                .decrypt_inout(&tweak, InOutBuf::from(&mut buffer[..]));
+            assert_eq!(buffer, msg);
+        }
+    }
+
+    /// Property-based tests of the invariants the modes rely on.
+    mod properties {
+        use std::prelude::v1::*;
+
+        use digest::{Update, XofReader};
+        use inout::InOutBuf;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+        use proptest::sample::Index;
+
+        use super::{XoofffSane, XoofffSanse, XoofffWbc};
+        use crate::SessionAead;
+        use crate::core_api::CoreWrapper;
+        use crate::deck::Deck;
+        use crate::instances::Xoofff;
+
+        const KEY: [u8; 32] = [0x42; 32];
+        const IV: [u8; 16] = [0x24; 16];
+
+        type XoofffDeck = CoreWrapper<Xoofff>;
+
+        /// Split `data` at the positions picked by `cuts`.
+        fn split<'a>(data: &'a [u8], cuts: &[Index]) -> Vec<&'a [u8]> {
+            let mut cuts: Vec<usize> = cuts.iter().map(|c| c.index(data.len() + 1)).collect();
+            cuts.sort_unstable();
+            cuts.push(data.len());
+
+            let mut start = 0;
+            cuts.into_iter()
+                .map(|end| {
+                    let chunk = &data[start..end];
+                    start = end;
+                    chunk
+                })
+                .collect()
+        }
+
+        fn squeeze(deck: &mut XoofffDeck) -> [u8; 100] {
+            let mut out = [0; 100];
+            deck.finalize_deck().read(&mut out);
+            out
+        }
+
+        fn check_roundtrip<S: SessionAead>(
+            mut enc: S,
+            mut dec: S,
+            messages: &[(Vec<u8>, Vec<u8>)],
+        ) -> Result<(), TestCaseError> {
+            for (ad, msg) in messages {
+                let mut buffer = msg.clone();
+                let tag = enc.encrypt_inout_detached(ad, InOutBuf::from(&mut buffer[..]));
+                dec.decrypt_inout_detached(ad, InOutBuf::from(&mut buffer[..]), &tag)
+                    .map_err(|_| TestCaseError::fail("valid message rejected"))?;
+                prop_assert_eq!(&buffer, msg);
+            }
+            Ok(())
+        }
+
+        /// Flip the bit at `bit` of the concatenation of the associated data, ciphertext and tag.
+        fn check_tamper<S: SessionAead>(
+            mut enc: S,
+            mut dec: S,
+            ad: &[u8],
+            msg: &[u8],
+            bit: Index,
+        ) -> Result<(), TestCaseError> {
+            let mut ct = msg.to_vec();
+            let tag = enc.encrypt_inout_detached(ad, InOutBuf::from(&mut ct[..]));
+
+            let mut fields = [ad.to_vec(), ct.clone(), tag.to_vec()];
+            let mut bit = bit.index(8 * fields.iter().map(Vec::len).sum::<usize>());
+            for field in &mut fields {
+                if bit < 8 * field.len() {
+                    field[bit / 8] ^= 1 << (bit % 8);
+                    break;
+                }
+                bit -= 8 * field.len();
+            }
+            let [bad_ad, mut bad_ct, bad_tag] = fields;
+            let bad_tag = bad_tag[..].try_into().unwrap();
+            prop_assert!(
+                dec.decrypt_inout_detached(&bad_ad, InOutBuf::from(&mut bad_ct[..]), &bad_tag)
+                    .is_err()
+            );
+
+            // the failure leaves the session untouched
+            dec.decrypt_inout_detached(ad, InOutBuf::from(&mut ct[..]), &tag)
+                .map_err(|_| TestCaseError::fail("valid message rejected"))?;
+            prop_assert_eq!(ct, msg);
+            Ok(())
+        }
+
+        fn message() -> impl Strategy<Value = (Vec<u8>, Vec<u8>)> {
+            (vec(any::<u8>(), 0..40), vec(any::<u8>(), 0..150))
+        }
+
+        proptest! {
+            #[test]
+            fn check_update_chunking(
+                data in vec(any::<u8>(), 0..300),
+                cuts in vec(any::<Index>(), 0..8),
+            ) {
+                let mut whole = XoofffDeck::init(&KEY);
+                whole.update(&data);
+
+                let mut chunked = XoofffDeck::init(&KEY);
+                for chunk in split(&data, &cuts) {
+                    chunked.update(chunk);
+                }
+                prop_assert_eq!(squeeze(&mut whole), squeeze(&mut chunked));
+            }
+
+            #[test]
+            fn check_update_after_finalize(
+                a in vec(any::<u8>(), 0..150),
+                b in vec(any::<u8>(), 0..150),
+                cuts in vec(any::<Index>(), 0..4),
+            ) {
+                let mut deck = XoofffDeck::init(&KEY);
+                deck.update(&a);
+                let mut first = deck.finalize_deck();
+                for chunk in split(&b, &cuts) {
+                    deck.update(chunk);
+                }
+                let second = squeeze(&mut deck);
+
+                // the output of the first string is not affected by the second
+                let mut out = [0; 100];
+                first.read(&mut out);
+                let mut only_a = XoofffDeck::init(&KEY);
+                only_a.update(&a);
+                prop_assert_eq!(out, squeeze(&mut only_a));
+
+                // the second string is absorbed as a new string, not appended to the first
+                let mut joined = XoofffDeck::init(&KEY);
+                joined.update(&a);
+                joined.update(&b);
+                prop_assert_ne!(second, squeeze(&mut joined));
+
+                #[cfg(feature = "reference")]
+                {
+                    use crate::instances::XoofffCore;
+                    use crate::reference::{Farfalle, bits, bytes};
+
+                    let f = Farfalle::<XoofffCore>::new(&KEY);
+                    prop_assert_eq!(&second[..], bytes(&f.output(&[bits(&a), bits(&b)], 0, 800)));
+                }
+            }
+
+            #[test]
+            fn check_sane_roundtrip(messages in vec(message(), 0..8)) {
+                check_roundtrip(
+                    XoofffSane::init(&KEY, &IV),
+                    XoofffSane::init(&KEY, &IV),
+                    &messages,
+                )?;
+            }
+
+            #[test]
+            fn check_sanse_roundtrip(messages in vec(message(), 0..8)) {
+                check_roundtrip(XoofffSanse::init(&KEY), XoofffSanse::init(&KEY), &messages)?;
+            }
+
+            #[test]
+            fn check_sane_tamper((ad, msg) in message(), bit in any::<Index>()) {
+                check_tamper(
+                    XoofffSane::init(&KEY, &IV),
+                    XoofffSane::init(&KEY, &IV),
+                    &ad,
+                    &msg,
+                    bit,
+                )?;
+            }
+
+            #[test]
+            fn check_sanse_tamper((ad, msg) in message(), bit in any::<Index>()) {
+                check_tamper(
+                    XoofffSanse::init(&KEY),
+                    XoofffSanse::init(&KEY),
+                    &ad,
+                    &msg,
+                    bit,
+                )?;
+            }
+
+            #[test]
+            fn check_wbc_inverse(
+                msg in vec(any::<u8>(), 0..400),
+                tweak in vec(any::<u8>(), 0..64),
+            ) {
+                let mut buffer = msg.clone();
+                XoofffWbc::init(&KEY).encrypt_inout(&tweak, InOutBuf::from(&mut buffer[..]));
+                if msg.len() >= 16 {
+                    prop_assert_ne!(&buffer, &msg);
+                }
+                XoofffWbc::init(&KEY).decrypt_inout(&tweak, InOutBuf::from(&mut buffer[..]));
+                prop_assert_eq!(buffer, msg);
+            }
+        }
     }
 }
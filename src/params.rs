@@ -0,0 +1,87 @@
+//! Runtime parameters.
+//!
+//! The typed constructions take their tag size and alignment as [`typenum`](digest::typenum)
+//! parameters. When these are only known at runtime, for example because they are negotiated by
+//! a wire protocol, the `Runtime*` variants take them as arguments instead and validate them
+//! when constructed:
+//!
+//! * [`RuntimeDeckSane`](crate::sane::RuntimeDeckSane)
+//! * [`RuntimeDeckSanse`](crate::sanse::RuntimeDeckSanse)
+//! * [`WideBlockCipher::with_alignment`](crate::wbc::WideBlockCipher::with_alignment) and
+//!   [`RuntimeWideBlockCipherAuthenticated`](crate::wbc::RuntimeWideBlockCipherAuthenticated)
+
+use core::fmt;
+
+use inout::InOutBuf;
+
+/// Minimum tag size in bytes accepted by the runtime-parameterized constructions.
+///
+/// Shorter tags give a forger a non-negligible chance of success over the lifetime of a key.
+pub const MIN_TAG_SIZE: usize = 8;
+
+/// Maximum tag size in bytes accepted by the runtime-parameterized constructions.
+pub const MAX_TAG_SIZE: usize = 64;
+
+/// Maximum alignment in bytes accepted by the runtime-parameterized constructions.
+///
+/// The keystream offset grows with the alignment, so larger values only cost permutation calls.
+pub const MAX_ALIGNMENT: usize = 256;
+
+/// Error returned when runtime parameters are not supported by a construction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidParameters;
+
+impl fmt::Display for InvalidParameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid tag size or alignment")
+    }
+}
+
+impl core::error::Error for InvalidParameters {}
+
+pub(crate) fn check_tag_size(tag_size: usize) -> Result<(), InvalidParameters> {
+    if (MIN_TAG_SIZE..=MAX_TAG_SIZE).contains(&tag_size) {
+        Ok(())
+    } else {
+        Err(InvalidParameters)
+    }
+}
+
+pub(crate) fn check_alignment(alignment: usize) -> Result<(), InvalidParameters> {
+    if (1..=MAX_ALIGNMENT).contains(&alignment) {
+        Ok(())
+    } else {
+        Err(InvalidParameters)
+    }
+}
+
+/// Encrypt the message in the buffer with a detached-tag `encrypt`, appending the tag.
+///
+/// Space for the tag is reserved first, so the session is untouched if the buffer cannot grow.
+pub(crate) fn encrypt_in_place(
+    buffer: &mut (impl aead::Buffer + ?Sized),
+    tag_size: usize,
+    encrypt: impl FnOnce(InOutBuf<'_, '_, u8>, &mut [u8]),
+) -> aead::Result<()> {
+    let msg_len = buffer.len();
+    buffer.extend_from_slice(&[0; MAX_TAG_SIZE][..tag_size])?;
+
+    let (msg, tag) = buffer.as_mut().split_at_mut(msg_len);
+    encrypt(msg.into(), tag);
+    Ok(())
+}
+
+/// Decrypt the message in the buffer with a detached-tag `decrypt`, removing the appended tag.
+pub(crate) fn decrypt_in_place(
+    buffer: &mut (impl aead::Buffer + ?Sized),
+    tag_size: usize,
+    decrypt: impl FnOnce(InOutBuf<'_, '_, u8>, &[u8]) -> aead::Result<()>,
+) -> aead::Result<()> {
+    let tagless_len = buffer.len().checked_sub(tag_size).ok_or(aead::Error)?;
+
+    let (msg, tag) = buffer.as_mut().split_at_mut(tagless_len);
+    decrypt(msg.into(), tag)?;
+
+    buffer.truncate(tagless_len);
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! Forward-secure rekeying.
+//!
+//! A [`Ratchet`] derives a new key from the session history, restarts the session under it and
+//! drops the old state. Once both ends have ratcheted, compromising the session state no longer
+//! exposes the messages exchanged before the ratchet.
+//!
+//! The key is derived from the history with a frame that no message can produce, so it is
+//! independent of every tag and keystream of the session. Both ends must ratchet at the same
+//! point in the session, either explicitly or with [`AutoRatchet`] and the same
+//! [`RatchetPolicy`].
+//!
+//! The new state is assigned over the old one, and the derived key is always wiped. With the
+//! `zeroize` feature the old state, and the copy of the history the key is derived from, are also
+//! zeroized as they are dropped. Without it, copies the compiler made along the way may remain in
+//! memory.
+
+use digest::consts::U32;
+use hybrid_array::Array;
+use inout::InOutBuf;
+
+use crate::SessionAead;
+use crate::deck::DeckCore;
+use crate::sanse::apply_padded;
+
+/// A session that can be rekeyed from its own history.
+pub trait Ratchet {
+    /// Derive a new key from the history, restart the session under it and erase the old state.
+    fn ratchet(&mut self);
+}
+
+/// Derive the next key from a copy of the history `d`, with `e` the current session bit.
+///
+/// The caller restarts the session under the key and then wipes it with [`wipe`](crate::core_api::wipe).
+pub(crate) fn next_key<D: DeckCore + Clone>(d: &D, e: u8) -> Array<u8, U32> {
+    // Deck-SANE only uses two bit frames and Deck-SANSE uses 0b01x and 0b11x,
+    // so this three bit frame is unused by messages in either construction.
+    apply_padded::<_, 3>(&mut d.clone(), &[], 0b100 | e).read_tag()
+}
+
+/// When an [`AutoRatchet`] session ratchets.
+///
+/// The session ratchets after the message that reaches either limit. Failed decryptions do not
+/// count towards the limits, as they leave the session untouched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RatchetPolicy {
+    /// Ratchet after this many messages
+    pub messages: Option<u64>,
+    /// Ratchet after this many bytes of message
+    pub bytes: Option<u64>,
+}
+
+impl RatchetPolicy {
+    /// Ratchet after every `n` messages.
+    pub const fn every_messages(n: u64) -> Self {
+        Self {
+            messages: Some(n),
+            bytes: None,
+        }
+    }
+
+    /// Ratchet after every `n` bytes of messages.
+    pub const fn every_bytes(n: u64) -> Self {
+        Self {
+            messages: None,
+            bytes: Some(n),
+        }
+    }
+
+    fn is_due(&self, messages: u64, bytes: u64) -> bool {
+        self.messages.is_some_and(|n| messages >= n) || self.bytes.is_some_and(|n| bytes >= n)
+    }
+}
+
+/// A session that ratchets automatically according to a [`RatchetPolicy`].
+pub struct AutoRatchet<S> {
+    session: S,
+    policy: RatchetPolicy,
+    /// messages since the last ratchet
+    messages: u64,
+    /// bytes of messages since the last ratchet
+    bytes: u64,
+}
+
+impl<S: Ratchet> AutoRatchet<S> {
+    pub fn new(session: S, policy: RatchetPolicy) -> Self {
+        Self {
+            session,
+            policy,
+            messages: 0,
+            bytes: 0,
+        }
+    }
+
+    pub fn policy(&self) -> RatchetPolicy {
+        self.policy
+    }
+
+    pub fn into_inner(self) -> S {
+        self.session
+    }
+
+    /// Count a processed message, ratcheting if the policy is due.
+    fn record(&mut self, len: usize) {
+        self.messages += 1;
+        self.bytes = self.bytes.saturating_add(len as u64);
+        if self.policy.is_due(self.messages, self.bytes) {
+            self.session.ratchet();
+            self.messages = 0;
+            self.bytes = 0;
+        }
+    }
+}
+
+impl<S: Ratchet> Ratchet for AutoRatchet<S> {
+    fn ratchet(&mut self) {
+        self.session.ratchet();
+        self.messages = 0;
+        self.bytes = 0;
+    }
+}
+
+impl<S: SessionAead + Ratchet> SessionAead for AutoRatchet<S> {
+    type Tag = S::Tag;
+
+    fn encrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+    ) -> Array<u8, Self::Tag> {
+        let len = buffer.len();
+        let tag = self.session.encrypt_vectored(associated_data, buffer);
+        self.record(len);
+        tag
+    }
+
+    fn decrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &Array<u8, Self::Tag>,
+    ) -> aead::Result<()> {
+        let len = buffer.len();
+        self.session
+            .decrypt_vectored(associated_data, buffer, tag)?;
+        self.record(len);
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+//! Slow, bit-oriented reference implementation.
+//!
+//! This module follows the pseudocode of the Farfalle paper
+//! <https://tosc.iacr.org/index.php/ToSC/article/view/855> and the Xoodoo cookbook
+//! <https://eprint.iacr.org/2018/767> as literally as possible, to check the optimized
+//! implementations against:
+//!
+//! * strings are sequences of bits, and every string carries its frame bits explicitly,
+//! * pad10\* is applied to whole strings, which are then split into blocks,
+//! * the deck output is recomputed from the key and the full sequence of strings on every call,
+//!   instead of keeping any state or buffering partial blocks.
+//!
+//! Byte strings are converted to bit strings least significant bit first: bit `i` of byte `j` is
+//! bit `8j + i` of the string, as specified for Xoodoo and Keccak-p and used by the XKCP. This
+//! matches the little-endian lanes of the permutations, and the frame bits of each string follow
+//! its last bit in that order. The Deck-SANE offset is the tag length rounded up to a multiple of
+//! the alignment, `ℓ⌈t/ℓ⌉` in the paper. Neither is taken from the optimized implementations.
+//!
+//! Nothing here is constant time or fast, it is only meant for testing.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use digest::typenum::Unsigned;
+use hybrid_array::Array;
+
+use crate::Permutation;
+use crate::farfalle::FarfalleCore;
+
+/// A string of bits.
+pub type Bits = Vec<bool>;
+
+/// The bits of `bytes`, least significant bit first.
+pub fn bits(bytes: &[u8]) -> Bits {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1 == 1))
+        .collect()
+}
+
+/// The bytes of `bits`, least significant bit first.
+///
+/// # Panics
+///
+/// If the length of `bits` is not a multiple of 8.
+pub fn bytes(bits: &[bool]) -> Vec<u8> {
+    assert!(bits.len().is_multiple_of(8), "not a whole number of bytes");
+    bits.chunks(8)
+        .map(|byte| {
+            byte.iter()
+                .enumerate()
+                .fold(0, |acc, (i, &bit)| acc | (bit as u8) << i)
+        })
+        .collect()
+}
+
+/// `m || 1 || 0*`, up to the next multiple of `b` bits.
+pub fn pad10(m: &[bool], b: usize) -> Bits {
+    let mut padded = m.to_vec();
+    padded.push(true);
+    while !padded.len().is_multiple_of(b) {
+        padded.push(false);
+    }
+    padded
+}
+
+/// `m || frame`, a string with its frame bits.
+fn framed(m: &[bool], frame: &[bool]) -> Bits {
+    let mut s = m.to_vec();
+    s.extend_from_slice(frame);
+    s
+}
+
+fn xor(a: &[bool], b: &[bool]) -> Bits {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
+
+/// Add `b` to the first bits of `a`.
+fn xor_prefix(a: &mut [bool], b: &[bool]) {
+    for (a, b) in a.iter_mut().zip(b) {
+        *a ^= b;
+    }
+}
+
+/// Apply the permutation `P` to a state of `b` bits.
+fn permute<P: Permutation>(state: &[bool]) -> Bits {
+    let mut block = Array::<u8, P::Size>::try_from(&bytes(state)[..]).expect("state size");
+    P::permute(&mut block);
+    bits(&block)
+}
+
+/// The Farfalle construction, as a function of the key and the sequence of input strings.
+pub struct Farfalle<C: FarfalleCore> {
+    key: Bits,
+    _core: PhantomData<C>,
+}
+
+impl<C: FarfalleCore> Farfalle<C> {
+    /// The state width `b` in bits.
+    pub const WIDTH: usize = 8 * <C::StateSize as Unsigned>::USIZE;
+
+    /// # Panics
+    ///
+    /// If the key does not fit in a block with its padding.
+    pub fn new(key: &[u8]) -> Self {
+        assert!(8 * key.len() < Self::WIDTH, "key too long");
+        Self {
+            key: bits(key),
+            _core: PhantomData,
+        }
+    }
+
+    /// `F_K(M^(n) ∘ ... ∘ M^(1)) << offset`, truncated to `len` bits, where `strings` is
+    /// `[M^(1), ..., M^(n)]`.
+    pub fn output(&self, strings: &[Bits], offset: usize, len: usize) -> Bits {
+        let b = Self::WIDTH;
+
+        // mask derivation
+        let mut k = permute::<C::Pb>(&pad10(&self.key, b));
+
+        // compression, with the mask rolled after every block and once more after every string
+        let mut x = vec![false; b];
+        for m in strings {
+            for block in pad10(m, b).chunks(b) {
+                x = xor(&x, &permute::<C::Pc>(&xor(block, &k)));
+                k = permute::<C::Rc>(&k);
+            }
+            k = permute::<C::Rc>(&k);
+        }
+
+        // expansion
+        let mut y = permute::<C::Pd>(&x);
+        let mut z = Bits::new();
+        while z.len() < offset + len {
+            z.extend(xor(&permute::<C::Pe>(&y), &k));
+            y = permute::<C::Re>(&y);
+        }
+        z[offset..offset + len].to_vec()
+    }
+}
+
+/// Deck-SANE, with tags of `tag_size` bytes and the keystream starting after the tag, rounded up
+/// to a multiple of `alignment` bytes.
+pub struct Sane<C: FarfalleCore> {
+    f: Farfalle<C>,
+    history: Vec<Bits>,
+    e: bool,
+    tag_len: usize,
+    offset: usize,
+}
+
+impl<C: FarfalleCore> Sane<C> {
+    /// Start a session with `key` and `nonce`.
+    pub fn new(key: &[u8], nonce: &[u8], tag_size: usize, alignment: usize) -> Self {
+        Self {
+            f: Farfalle::new(key),
+            history: vec![bits(nonce)],
+            e: false,
+            tag_len: 8 * tag_size,
+            // ℓ⌈t/ℓ⌉
+            offset: 8 * alignment * tag_size.div_ceil(alignment),
+        }
+    }
+
+    /// The strings of a message appended to `history`.
+    fn strings(&self, ad: &[u8], ct: &[bool]) -> Vec<Bits> {
+        let mut strings = Vec::new();
+        if !ad.is_empty() || ct.is_empty() {
+            strings.push(framed(&bits(ad), &[false, self.e]));
+        }
+        if !ct.is_empty() {
+            strings.push(framed(ct, &[true, self.e]));
+        }
+        strings
+    }
+
+    /// Returns the ciphertext and the tag.
+    pub fn encrypt(&mut self, ad: &[u8], pt: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let p = bits(pt);
+        let c = xor(&p, &self.f.output(&self.history, self.offset, p.len()));
+
+        let strings = self.strings(ad, &c);
+        self.history.extend(strings);
+        let t = self.f.output(&self.history, 0, self.tag_len);
+        self.e = !self.e;
+
+        (bytes(&c), bytes(&t))
+    }
+
+    /// Returns `None`, leaving the session unchanged, if the tag is invalid.
+    pub fn decrypt(&mut self, ad: &[u8], ct: &[u8], tag: &[u8]) -> Option<Vec<u8>> {
+        let c = bits(ct);
+        let p = xor(&c, &self.f.output(&self.history, self.offset, c.len()));
+
+        let mut history = self.history.clone();
+        history.extend(self.strings(ad, &c));
+        if bits(tag) != self.f.output(&history, 0, self.tag_len) {
+            return None;
+        }
+        self.history = history;
+        self.e = !self.e;
+
+        Some(bytes(&p))
+    }
+}
+
+/// Deck-SANSE, with tags of `tag_size` bytes.
+pub struct Sanse<C: FarfalleCore> {
+    f: Farfalle<C>,
+    history: Vec<Bits>,
+    e: bool,
+    tag_len: usize,
+}
+
+impl<C: FarfalleCore> Sanse<C> {
+    /// Start a session with `key`.
+    pub fn new(key: &[u8], tag_size: usize) -> Self {
+        Self {
+            f: Farfalle::new(key),
+            history: Vec::new(),
+            e: false,
+            tag_len: 8 * tag_size,
+        }
+    }
+
+    /// The history with the associated data and the plaintext of a message applied.
+    fn history_with(&self, ad: &[u8], p: &[bool]) -> Vec<Bits> {
+        let mut history = self.history.clone();
+        if !ad.is_empty() || p.is_empty() {
+            history.push(framed(&bits(ad), &[false, self.e]));
+        }
+        if !p.is_empty() {
+            history.push(framed(p, &[false, true, self.e]));
+        }
+        history
+    }
+
+    /// The keystream of a message, from the history with the associated data and the tag.
+    fn keystream(&self, ad: &[u8], t: &[bool], len: usize) -> Bits {
+        let mut history = self.history.clone();
+        if !ad.is_empty() {
+            history.push(framed(&bits(ad), &[false, self.e]));
+        }
+        history.push(framed(t, &[true, true, self.e]));
+        self.f.output(&history, 0, len)
+    }
+
+    /// Returns the ciphertext and the tag.
+    pub fn encrypt(&mut self, ad: &[u8], pt: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let p = bits(pt);
+        let history = self.history_with(ad, &p);
+        let t = self.f.output(&history, 0, self.tag_len);
+        let c = if p.is_empty() {
+            Bits::new()
+        } else {
+            xor(&p, &self.keystream(ad, &t, p.len()))
+        };
+
+        self.history = history;
+        self.e = !self.e;
+        (bytes(&c), bytes(&t))
+    }
+
+    /// Returns `None`, leaving the session unchanged, if the tag is invalid.
+    pub fn decrypt(&mut self, ad: &[u8], ct: &[u8], tag: &[u8]) -> Option<Vec<u8>> {
+        let c = bits(ct);
+        let t = bits(tag);
+        let p = if c.is_empty() {
+            Bits::new()
+        } else {
+            xor(&c, &self.keystream(ad, &t, c.len()))
+        };
+
+        let history = self.history_with(ad, &p);
+        if t != self.f.output(&history, 0, self.tag_len) {
+            return None;
+        }
+        self.history = history;
+        self.e = !self.e;
+        Some(bytes(&p))
+    }
+}
+
+/// The Farfalle-WBC wide block cipher, with the deck functions `G` and `H` under the same key and
+/// the split aligned to `alignment` bytes.
+pub struct Wbc<G: FarfalleCore, H: FarfalleCore> {
+    g: Farfalle<G>,
+    h: Farfalle<H>,
+    alignment: usize,
+}
+
+impl<G: FarfalleCore, H: FarfalleCore> Wbc<G, H> {
+    pub fn new(key: &[u8], alignment: usize) -> Self {
+        Self {
+            g: Farfalle::new(key),
+            h: Farfalle::new(key),
+            alignment,
+        }
+    }
+
+    /// The length in bits of the left part of an `n` bit input.
+    fn split(&self, n: usize) -> usize {
+        let b = Farfalle::<H>::WIDTH;
+        let l = 8 * self.alignment;
+        if n <= 2 * b - (l + 2) {
+            l * ((n + l) / (2 * l))
+        } else {
+            let q = (n + l + 2).div_ceil(b);
+            // the largest x with 2^x < q
+            let mut x = 0;
+            while 1 << (x + 1) < q {
+                x += 1;
+            }
+            (q - (1 << x)) * b - l
+        }
+    }
+
+    /// Add `H_K(m)` to the first block of `x`.
+    fn xor_first_block(&self, x: &mut [bool], m: Bits) {
+        let n = x.len().min(Farfalle::<H>::WIDTH);
+        xor_prefix(x, &self.h.output(&[m], 0, n));
+    }
+
+    pub fn encrypt(&self, tweak: &[u8], pt: &[u8]) -> Vec<u8> {
+        let p = bits(pt);
+        let w = bits(tweak);
+        let (l, r) = p.split_at(self.split(p.len()));
+        let (mut l, mut r) = (l.to_vec(), r.to_vec());
+
+        self.xor_first_block(&mut r, framed(&l, &[false]));
+        l = xor(
+            &l,
+            &self.g.output(&[w.clone(), framed(&r, &[true])], 0, l.len()),
+        );
+        r = xor(&r, &self.g.output(&[w, framed(&l, &[false])], 0, r.len()));
+        self.xor_first_block(&mut l, framed(&r, &[true]));
+
+        l.extend(r);
+        bytes(&l)
+    }
+
+    pub fn decrypt(&self, tweak: &[u8], ct: &[u8]) -> Vec<u8> {
+        let c = bits(ct);
+        let w = bits(tweak);
+        let (l, r) = c.split_at(self.split(c.len()));
+        let (mut l, mut r) = (l.to_vec(), r.to_vec());
+
+        self.xor_first_block(&mut l, framed(&r, &[true]));
+        r = xor(
+            &r,
+            &self
+                .g
+                .output(&[w.clone(), framed(&l, &[false])], 0, r.len()),
+        );
+        l = xor(&l, &self.g.output(&[w, framed(&r, &[true])], 0, l.len()));
+        self.xor_first_block(&mut r, framed(&l, &[false]));
+
+        l.extend(r);
+        bytes(&l)
+    }
+}
@@ -1,30 +1,42 @@
 //! Deck-SANE construction.
 
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
 use aead::Error;
+use cipher::AlgorithmName;
 use crypto_common::BlockSizeUser;
 use digest::XofReader;
-use digest::core_api::{Buffer, ExtendableOutputCore, UpdateCore};
+use digest::core_api::{Buffer, ExtendableOutputCore, UpdateCore, XofReaderCore};
 use digest::typenum::Unsigned;
 use hybrid_array::{Array, ArraySize};
 use inout::InOutBuf;
 use subtle::ConstantTimeEq;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::SessionAead;
 use crate::core_api::{self, XofReaderCoreWrapper};
-use crate::deck::DeckCore;
+use crate::deck::{self, DeckCore};
+use crate::params::{self, InvalidParameters, MAX_TAG_SIZE};
+use crate::ratchet::{self, Ratchet};
+#[cfg(feature = "alloc")]
+use crate::snapshot::{self, write_state};
+use crate::snapshot::{Header, Mode, SerializeState, SnapshotError};
 
 /// Core parameters used by [`DeckSane`]
 pub trait DeckSaneCore {
     /// The [`DeckCore`] implementation to use
-    type Core: DeckCore;
+    type Core: DeckCore + Clone;
     /// The size of the tags the SAE impl produces
     type TagSize: ArraySize;
     /// The alignment of the key stream after the tag.
     type Alignnemt: ArraySize;
 }
 
-type XofReaderW<D> =
-    XofReaderCoreWrapper<<<D as DeckSaneCore>::Core as ExtendableOutputCore>::ReaderCore>;
+type ReaderCore<D> = <<D as DeckSaneCore>::Core as ExtendableOutputCore>::ReaderCore;
+type ReaderBlockSize<D> = <ReaderCore<D> as BlockSizeUser>::BlockSize;
+type XofReaderW<D> = XofReaderCoreWrapper<ReaderCore<D>>;
 
 /// Deck-SANE is a session-aware authenticated encryption ([`SessionAEAD`]) construction based on [`Deck`](DeckCore) functions.
 pub struct DeckSane<D: DeckSaneCore> {
@@ -38,81 +50,603 @@ pub struct DeckSane<D: DeckSaneCore> {
 
 impl<D: DeckSaneCore> DeckSane<D> {
     pub fn init(key: &[u8], iv: &[u8]) -> Self {
-        let mut d = <D::Core as DeckCore>::init(key);
-
-        // apply IV to history.
-        let mut buffer = Buffer::<D::Core>::new(&[]);
-        buffer.digest_blocks(iv, |b| d.update_blocks(b));
-        let mut k = XofReaderCoreWrapper::from_core(d.finalize_xof_core(&mut buffer));
+        let (d, mut k) = init_state::<D::Core>(key, iv);
         Self::consume_tag_and_offset(&mut k);
 
         Self { d, k, e: 0 }
     }
 
+    /// The number of bytes of each keystream taken up by the tag and alignment offset.
+    fn keystream_offset() -> usize {
+        keystream_offset(
+            <D::TagSize as Unsigned>::USIZE,
+            <D::Alignnemt as Unsigned>::USIZE,
+        )
+    }
+
     fn consume_tag_and_offset(k: &mut XofReaderW<D>) -> Array<u8, D::TagSize> {
         let mut tag = Array::<u8, D::TagSize>::default();
-        let mut offset =
-            <D::Alignnemt as Unsigned>::USIZE.next_multiple_of(<D::TagSize as Unsigned>::USIZE);
+        read_tag_and_offset(k, &mut tag, Self::keystream_offset());
+        tag
+    }
+}
+
+impl<D: DeckSaneCore> Ratchet for DeckSane<D> {
+    /// Restart the session under a key derived from the history, with an empty IV.
+    fn ratchet(&mut self) {
+        let mut key = ratchet::next_key(&self.d, self.e);
+        *self = Self::init(&key, &[]);
+        core_api::wipe(&mut key);
+    }
+}
+
+/// Apply the key and the IV to a new history, returning it with the first keystream.
+fn init_state<C: DeckCore>(key: &[u8], iv: &[u8]) -> (C, XofReaderCoreWrapper<C::ReaderCore>) {
+    let mut d = <C as DeckCore>::init(key);
+
+    // apply IV to history.
+    let mut buffer = Buffer::<C>::new(&[]);
+    buffer.digest_blocks(iv, |b| d.update_blocks(b));
+    let k = XofReaderCoreWrapper::from_core(d.finalize_xof_core(&mut buffer));
+    (d, k)
+}
+
+/// The number of bytes of each keystream taken up by the tag and alignment offset: the tag size
+/// rounded up to a multiple of the alignment.
+fn keystream_offset(tag_size: usize, alignment: usize) -> usize {
+    tag_size.next_multiple_of(alignment)
+}
+
+/// Read the tag from the start of a keystream and skip ahead to `offset`.
+fn read_tag_and_offset<R: XofReaderCore>(
+    k: &mut XofReaderCoreWrapper<R>,
+    tag: &mut [u8],
+    offset: usize,
+) {
+    k.read(tag);
+    let mut offset = offset - tag.len();
+
+    let mut spare = Array::<u8, R::BlockSize>::default();
+    while offset > <R::BlockSize as Unsigned>::USIZE {
+        k.read(&mut spare[..]);
+        offset -= <R::BlockSize as Unsigned>::USIZE;
+    }
+    if offset > 0 {
+        k.read(&mut spare[..offset]);
+    }
+    core_api::wipe_block(&mut spare);
+}
+
+impl<D: DeckSaneCore + AlgorithmName> DeckSane<D>
+where
+    D::Core: SerializeState,
+    ReaderCore<D>: SerializeState + Clone,
+{
+    fn snapshot_header() -> Header {
+        Header {
+            mode: Mode::Sane,
+            tag_size: <D::TagSize as Unsigned>::USIZE,
+            alignment: <D::Alignnemt as Unsigned>::USIZE,
+            block_size: <ReaderBlockSize<D> as Unsigned>::USIZE,
+        }
+    }
+
+    /// Export the full session state. See [`snapshot`](crate::snapshot) for the format.
+    ///
+    /// The snapshot contains secret key material, and must be imported at most once: encrypting
+    /// with two sessions imported from the same snapshot, or with this session and one imported
+    /// from its snapshot, reuses the keystream.
+    #[cfg(feature = "alloc")]
+    pub fn export(&self) -> Vec<u8> {
+        let mut out = Self::snapshot_header().write::<D>();
+        out.push(self.e);
+        write_state(&mut out, &self.d);
 
-        k.read(&mut tag[..]);
-        offset = offset - <D::TagSize as Unsigned>::USIZE;
+        // Between messages the keystream has always had the tag and offset read from it,
+        // so only the reader core and the unread part of its current block need saving.
+        let mut k = self.k.clone();
+        let pos = Self::keystream_offset() % <ReaderBlockSize<D> as Unsigned>::USIZE;
+        let mut rest = Array::<u8, ReaderBlockSize<D>>::default();
+        if pos > 0 {
+            k.read(&mut rest[pos..]);
+        }
+        write_state(&mut out, &k.core);
+        out.extend_from_slice(&rest[pos..]);
+
+        out
+    }
 
-        let mut spare = Array::<u8, <D::Core as BlockSizeUser>::BlockSize>::default();
-        while offset > <<D::Core as BlockSizeUser>::BlockSize as Unsigned>::USIZE {
-            k.read(&mut tag[..]);
-            offset = offset - <<D::Core as BlockSizeUser>::BlockSize as Unsigned>::USIZE;
+    /// Import a session exported with [`DeckSane::export`].
+    ///
+    /// Importing the same snapshot twice and encrypting with both sessions reuses the keystream.
+    pub fn import(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        let mut r = Self::snapshot_header().read::<D>(snapshot)?;
+        let e = r.byte()?;
+        if e > 1 {
+            return Err(SnapshotError::Malformed);
         }
-        if offset > 0 {
-            k.read(&mut spare[..offset]);
+        let d = r.state()?;
+
+        let mut k = XofReaderCoreWrapper::from_core(r.state()?);
+        let pos = Self::keystream_offset() % <ReaderBlockSize<D> as Unsigned>::USIZE;
+        if pos > 0 {
+            // restore the unread part of the current block into the read buffer
+            let rest = r.take(<ReaderBlockSize<D> as Unsigned>::USIZE - pos)?;
+            let mut skip = Array::<u8, ReaderBlockSize<D>>::default();
+            k.buffer
+                .read(&mut skip[..pos], |block| block[pos..].copy_from_slice(rest));
         }
+        r.finish()?;
 
-        tag
+        Ok(Self { d, k, e })
     }
+
+    /// Export the full session state, sealed under `wrapping_key`.
+    #[cfg(feature = "alloc")]
+    pub fn export_sealed(&self, wrapping_key: &[u8]) -> Vec<u8> {
+        snapshot::seal::<D::Core>(wrapping_key, self.export())
+    }
+
+    /// Import a session exported with [`DeckSane::export_sealed`].
+    ///
+    /// As with [`DeckSane::import`], each sealed snapshot must be imported at most once.
+    #[cfg(feature = "alloc")]
+    pub fn import_sealed(wrapping_key: &[u8], sealed: &[u8]) -> Result<Self, SnapshotError> {
+        Self::import(&snapshot::open::<D::Core>(wrapping_key, sealed)?)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<D: DeckSaneCore> Zeroize for DeckSane<D>
+where
+    D::Core: Zeroize,
+    ReaderCore<D>: Zeroize,
+{
+    fn zeroize(&mut self) {
+        self.d.zeroize();
+        self.k.zeroize();
+        self.e.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<D: DeckSaneCore> ZeroizeOnDrop for DeckSane<D>
+where
+    D::Core: ZeroizeOnDrop,
+    ReaderCore<D>: ZeroizeOnDrop,
+{
 }
 
 impl<D: DeckSaneCore> SessionAead for DeckSane<D> {
     type Tag = D::TagSize;
 
-    fn encrypt_inout_detached(
+    fn encrypt_vectored(
         &mut self,
-        ad: &[u8],
-        mut buffer: InOutBuf<'_, '_, u8>,
+        ad: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
     ) -> Array<u8, Self::Tag> {
-        // apply keystream to plaintext
-        core_api::xor(&mut self.k, buffer.reborrow());
-        let ciphertext = buffer.into_out();
+        let mut tag = Array::<u8, D::TagSize>::default();
+        let Self { d, k, e } = self;
+        encrypt_message(d, k, e, ad, buffer, &mut tag, Self::keystream_offset());
+        tag
+    }
 
-        let mut k = apply_ad_ct(&mut self.d, self.e, ad, ciphertext);
-        self.e ^= 0x01;
+    fn decrypt_vectored(
+        &mut self,
+        ad: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &Array<u8, Self::Tag>,
+    ) -> Result<(), Error> {
+        let mut actual_tag = Array::<u8, D::TagSize>::default();
+        let Self { d, k, e } = self;
+        decrypt_message(
+            d,
+            k,
+            e,
+            ad,
+            buffer,
+            tag,
+            &mut actual_tag,
+            Self::keystream_offset(),
+        )
+    }
+}
 
-        let tag = Self::consume_tag_and_offset(&mut k);
+/// Encrypt a message and write its tag, moving the session state forward.
+fn encrypt_message<C: DeckCore>(
+    d: &mut C,
+    k: &mut XofReaderCoreWrapper<C::ReaderCore>,
+    e: &mut u8,
+    ad: &[&[u8]],
+    mut buffer: InOutBuf<'_, '_, u8>,
+    tag: &mut [u8],
+    offset: usize,
+) {
+    // apply keystream to plaintext
+    core_api::xor(k, buffer.reborrow());
+    let ciphertext = buffer.into_out();
 
-        self.k = k;
+    let mut next = apply_ad_ct(d, *e, ad, ciphertext);
+    *e ^= 0x01;
 
-        tag
+    read_tag_and_offset(&mut next, tag, offset);
+
+    *k = next;
+}
+
+/// Decrypt a message, moving the session state forward only if the tag is valid.
+///
+/// `actual_tag` is scratch space of the same length as `tag`.
+#[allow(clippy::too_many_arguments)]
+fn decrypt_message<C: DeckCore + Clone>(
+    d: &mut C,
+    k: &mut XofReaderCoreWrapper<C::ReaderCore>,
+    e: &mut u8,
+    ad: &[&[u8]],
+    buffer: InOutBuf<'_, '_, u8>,
+    tag: &[u8],
+    actual_tag: &mut [u8],
+    offset: usize,
+) -> Result<(), Error> {
+    // apply associated data to a copy of the history, only committed if the tag is valid
+    let ciphertext = buffer.get_in();
+
+    let mut next_d = d.clone();
+    let mut next_k = apply_ad_ct(&mut next_d, *e, ad, ciphertext);
+
+    read_tag_and_offset(&mut next_k, actual_tag, offset);
+    if tag.ct_ne(actual_tag).into() {
+        return Err(Error);
+    }
+
+    // apply keystream to ciphertext
+    core_api::xor(k, buffer);
+    *d = next_d;
+    *e ^= 0x01;
+    *k = next_k;
+
+    Ok(())
+}
+
+/// [`DeckSane`] with the tag size and alignment chosen at runtime.
+///
+/// Messages are processed identically to a [`DeckSane`] with the same parameters.
+pub struct RuntimeDeckSane<C: DeckCore> {
+    /// the history
+    d: C,
+    /// the state of the next keystream
+    k: XofReaderCoreWrapper<C::ReaderCore>,
+    /// u1 that is stored in bit 1 (0x01)
+    e: u8,
+    tag_size: usize,
+    /// the number of bytes of each keystream taken up by the tag and alignment offset
+    offset: usize,
+}
+
+impl<C: DeckCore + Clone> RuntimeDeckSane<C> {
+    /// Start a session producing `tag_size` byte tags, with the keystream after each tag
+    /// aligned to `alignment` bytes.
+    ///
+    /// The tag size must be between [`MIN_TAG_SIZE`](params::MIN_TAG_SIZE) and [`MAX_TAG_SIZE`],
+    /// and the alignment between 1 and [`MAX_ALIGNMENT`](params::MAX_ALIGNMENT).
+    pub fn init(
+        key: &[u8],
+        iv: &[u8],
+        tag_size: usize,
+        alignment: usize,
+    ) -> Result<Self, InvalidParameters> {
+        params::check_tag_size(tag_size)?;
+        params::check_alignment(alignment)?;
+        let offset = keystream_offset(tag_size, alignment);
+
+        let (d, mut k) = init_state::<C>(key, iv);
+        read_tag_and_offset(&mut k, &mut [0; MAX_TAG_SIZE][..tag_size], offset);
+
+        Ok(Self {
+            d,
+            k,
+            e: 0,
+            tag_size,
+            offset,
+        })
     }
 
-    fn decrypt_inout_detached(
+    /// The size of the tags in bytes.
+    pub fn tag_size(&self) -> usize {
+        self.tag_size
+    }
+
+    /// Encrypt the message in the buffer, writing the tag to `tag`.
+    ///
+    /// # Panics
+    ///
+    /// If `tag` is not [`tag_size`](Self::tag_size) bytes long.
+    pub fn encrypt_inout_detached(
         &mut self,
-        ad: &[u8],
+        associated_data: &[u8],
         buffer: InOutBuf<'_, '_, u8>,
-        tag: &Array<u8, Self::Tag>,
+        tag: &mut [u8],
+    ) {
+        self.encrypt_vectored(&[associated_data], buffer, tag)
+    }
+
+    /// Decrypt the message in the buffer, returning an error if the tag is invalid.
+    pub fn decrypt_inout_detached(
+        &mut self,
+        associated_data: &[u8],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &[u8],
     ) -> Result<(), Error> {
-        // apply associated data to history
-        let ciphertext = buffer.get_in();
+        self.decrypt_vectored(&[associated_data], buffer, tag)
+    }
 
-        let mut k = apply_ad_ct(&mut self.d, self.e, ad, ciphertext);
-        self.e ^= 0x01;
+    /// Like [`RuntimeDeckSane::encrypt_inout_detached`], with the associated data split into
+    /// several fields. See [`SessionAead::encrypt_vectored`].
+    ///
+    /// # Panics
+    ///
+    /// If `tag` is not [`tag_size`](Self::tag_size) bytes long.
+    pub fn encrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &mut [u8],
+    ) {
+        assert_eq!(tag.len(), self.tag_size, "tag length mismatch");
+        let Self {
+            d, k, e, offset, ..
+        } = self;
+        encrypt_message(d, k, e, associated_data, buffer, tag, *offset);
+    }
 
-        let actual_tag = Self::consume_tag_and_offset(&mut k);
-        if tag.ct_ne(&actual_tag).into() {
+    /// Like [`RuntimeDeckSane::decrypt_inout_detached`], with the associated data split into
+    /// several fields. See [`SessionAead::encrypt_vectored`].
+    pub fn decrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        if tag.len() != self.tag_size {
             return Err(Error);
         }
+        let mut actual_tag = [0; MAX_TAG_SIZE];
+        let Self {
+            d,
+            k,
+            e,
+            tag_size,
+            offset,
+        } = self;
+        decrypt_message(
+            d,
+            k,
+            e,
+            associated_data,
+            buffer,
+            tag,
+            &mut actual_tag[..*tag_size],
+            *offset,
+        )
+    }
 
-        // apply keystream to ciphertext
-        core_api::xor(&mut self.k, buffer);
+    /// Encrypt the message in the buffer in-place, appending the tag.
+    /// See [`SessionAead::encrypt_in_place`].
+    pub fn encrypt_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut impl aead::Buffer,
+    ) -> aead::Result<()> {
+        params::encrypt_in_place(buffer, self.tag_size, |msg, tag| {
+            self.encrypt_inout_detached(associated_data, msg, tag)
+        })
+    }
+
+    /// Decrypt the message in the buffer in-place, checking the appended tag.
+    /// See [`SessionAead::decrypt_in_place`].
+    pub fn decrypt_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut impl aead::Buffer,
+    ) -> aead::Result<()> {
+        params::decrypt_in_place(buffer, self.tag_size, |msg, tag| {
+            self.decrypt_inout_detached(associated_data, msg, tag)
+        })
+    }
+}
+
+impl<C: DeckCore + Clone> Ratchet for RuntimeDeckSane<C> {
+    /// Restart the session under a key derived from the history, with an empty IV.
+    fn ratchet(&mut self) {
+        let mut key = ratchet::next_key(&self.d, self.e);
+        let (d, mut k) = init_state::<C>(&key, &[]);
+        read_tag_and_offset(&mut k, &mut [0; MAX_TAG_SIZE][..self.tag_size], self.offset);
+        self.d = d;
         self.k = k;
+        self.e = 0;
+        core_api::wipe(&mut key);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<C: DeckCore + Zeroize> Zeroize for RuntimeDeckSane<C>
+where
+    C::ReaderCore: Zeroize,
+{
+    fn zeroize(&mut self) {
+        self.d.zeroize();
+        self.k.zeroize();
+        self.e.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<C: DeckCore + ZeroizeOnDrop> ZeroizeOnDrop for RuntimeDeckSane<C> where
+    C::ReaderCore: ZeroizeOnDrop
+{
+}
+
+impl<D: DeckSaneCore> DeckSane<D>
+where
+    ReaderCore<D>: Clone,
+{
+    /// Start encrypting a message incrementally, for messages too large to hold in memory at once.
+    ///
+    /// The result is identical to [`SessionAead::encrypt_inout_detached`] on the concatenation
+    /// of all the chunks. The session moves forward when [`SaneEncryptor::finish`] is called, or
+    /// when the encryptor is dropped after producing ciphertext, so that its keystream is never
+    /// used again. The message is then lost, and the peer can no longer follow the session.
+    pub fn begin_encrypt(&mut self, associated_data: &[u8]) -> SaneEncryptor<'_, D> {
+        SaneEncryptor {
+            state: MessageState::begin(self, associated_data),
+            finished: false,
+        }
+    }
+
+    /// Start decrypting a message incrementally, for messages too large to hold in memory at once.
+    ///
+    /// Plaintext is released by [`SaneDecryptor::update`] before it has been authenticated, so it
+    /// must not be acted upon until [`SaneDecryptor::finish`] has verified the tag. The session
+    /// only moves forward if the tag is valid.
+    pub fn begin_decrypt(&mut self, associated_data: &[u8]) -> SaneDecryptor<'_, D> {
+        SaneDecryptor(MessageState::begin(self, associated_data))
+    }
+}
+
+/// State of a message being processed incrementally.
+/// It works on copies of the session state, which are committed in [`MessageState::commit`].
+struct MessageState<'a, D: DeckSaneCore> {
+    session: &'a mut DeckSane<D>,
+    /// the history, with the message applied so far
+    d: D::Core,
+    /// the keystream of this message
+    k: XofReaderW<D>,
+    /// buffered ciphertext that has not been applied to the history yet
+    buffer: Buffer<D::Core>,
+    /// the result of applying non-empty associated data to the history
+    ad: Option<XofReaderW<D>>,
+    /// whether any ciphertext has been applied
+    has_ciphertext: bool,
+}
+
+impl<'a, D: DeckSaneCore> MessageState<'a, D>
+where
+    ReaderCore<D>: Clone,
+{
+    fn begin(session: &'a mut DeckSane<D>, ad: &[u8]) -> Self {
+        let mut d = session.d.clone();
+        let k = session.k.clone();
+
+        // apply associated data to history
+        let ad = (!ad.is_empty()).then(|| apply_padded(&mut d, ad, 0x00 | session.e));
 
+        Self {
+            session,
+            d,
+            k,
+            buffer: Buffer::<D::Core>::new(&[]),
+            ad,
+            has_ciphertext: false,
+        }
+    }
+}
+
+impl<D: DeckSaneCore> MessageState<'_, D> {
+    fn absorb(&mut self, ciphertext: &[u8]) {
+        if !ciphertext.is_empty() {
+            self.has_ciphertext = true;
+            let Self { d, buffer, .. } = self;
+            buffer.digest_blocks(ciphertext, |b| d.update_blocks(b));
+        }
+    }
+
+    /// Finish applying the message to the history, returning the expected tag and the next keystream.
+    fn finish(&mut self) -> (Array<u8, D::TagSize>, XofReaderW<D>) {
+        let e = self.session.e;
+        let mut k = if self.has_ciphertext {
+            // apply ciphertext to history
+            XofReaderCoreWrapper::from_core(
+                self.d
+                    .finalize_deck_prepadded::<2>(&mut self.buffer, 0x02 | e),
+            )
+        } else {
+            match self.ad.take() {
+                Some(k) => k,
+                // apply empty associated data to history
+                None => apply_padded(&mut self.d, &[], 0x00 | e),
+            }
+        };
+
+        let tag = DeckSane::<D>::consume_tag_and_offset(&mut k);
+        (tag, k)
+    }
+
+    fn commit(&mut self, k: XofReaderW<D>) {
+        core::mem::swap(&mut self.session.d, &mut self.d);
+        self.session.e ^= 0x01;
+        self.session.k = k;
+    }
+}
+
+/// Incremental encryption of a single [`DeckSane`] message, see [`DeckSane::begin_encrypt`].
+pub struct SaneEncryptor<'a, D: DeckSaneCore> {
+    state: MessageState<'a, D>,
+    finished: bool,
+}
+
+impl<D: DeckSaneCore> SaneEncryptor<'_, D>
+where
+    ReaderCore<D>: Clone,
+{
+    /// Encrypt the next chunk of the message.
+    pub fn update(&mut self, mut buffer: InOutBuf<'_, '_, u8>) {
+        // apply keystream to plaintext
+        core_api::xor(&mut self.state.k, buffer.reborrow());
+        self.state.absorb(buffer.into_out());
+    }
+
+    /// Finish the message, returning the authentication tag and moving the session state forward.
+    pub fn finish(mut self) -> Array<u8, D::TagSize> {
+        let (tag, k) = self.state.finish();
+        self.state.commit(k);
+        self.finished = true;
+        tag
+    }
+}
+
+impl<D: DeckSaneCore> Drop for SaneEncryptor<'_, D> {
+    /// Move the session forward past an abandoned message that already produced ciphertext.
+    fn drop(&mut self) {
+        if !self.finished && self.state.has_ciphertext {
+            let (_, k) = self.state.finish();
+            self.state.commit(k);
+        }
+    }
+}
+
+/// Incremental decryption of a single [`DeckSane`] message, see [`DeckSane::begin_decrypt`].
+pub struct SaneDecryptor<'a, D: DeckSaneCore>(MessageState<'a, D>);
+
+impl<D: DeckSaneCore> SaneDecryptor<'_, D>
+where
+    ReaderCore<D>: Clone,
+{
+    /// Decrypt the next chunk of the message. The plaintext is not authenticated until
+    /// [`SaneDecryptor::finish`] succeeds.
+    pub fn update(&mut self, buffer: InOutBuf<'_, '_, u8>) {
+        self.0.absorb(buffer.get_in());
+        // apply keystream to ciphertext
+        core_api::xor(&mut self.0.k, buffer);
+    }
+
+    /// Verify the authentication tag of the whole message, moving the session state forward
+    /// only if it is valid.
+    pub fn finish(mut self, tag: &Array<u8, D::TagSize>) -> Result<(), Error> {
+        let (actual_tag, k) = self.0.finish();
+        if tag.ct_ne(&actual_tag).into() {
+            return Err(Error);
+        }
+        self.0.commit(k);
         Ok(())
     }
 }
@@ -120,22 +654,32 @@ impl<D: DeckSaneCore> SessionAead for DeckSane<D> {
 fn apply_ad_ct<D: DeckCore>(
     d: &mut D,
     e: u8,
-    ad: &[u8],
+    ad: &[&[u8]],
     ct: &[u8],
 ) -> XofReaderCoreWrapper<D::ReaderCore> {
     if ct.is_empty() {
         // apply associated data to history
-        apply_padded(d, ad, 0x00 | e)
+        apply_ad(d, e, ad)
     } else {
-        if !ad.is_empty() {
+        if !is_empty_ad(ad) {
             // apply associated data to history
-            apply_padded(d, ad, 0x00 | e);
+            apply_ad(d, e, ad);
         }
         // apply ciphertext to history
         apply_padded(d, ct, 0x02 | e)
     }
 }
 
+/// Whether the associated data is equivalent to a single empty string.
+pub(crate) fn is_empty_ad(ad: &[&[u8]]) -> bool {
+    matches!(ad, [] | [[]])
+}
+
+/// Apply the associated data to the history, see [`deck::apply_ad`].
+fn apply_ad<D: DeckCore>(d: &mut D, e: u8, ad: &[&[u8]]) -> XofReaderCoreWrapper<D::ReaderCore> {
+    XofReaderCoreWrapper::from_core(deck::apply_ad(d, e, ad))
+}
+
 #[inline(always)]
 pub(crate) fn apply_padded<D: DeckCore>(
     d: &mut D,
@@ -1,14 +1,28 @@
 //! Deck-SANSE construction.
 
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
 use aead::Error;
-use cipher::Block;
+use cipher::{AlgorithmName, Block};
+use crypto_common::BlockSizeUser;
 use digest::core_api::{Buffer, ExtendableOutputCore, XofReaderCore};
+use digest::typenum::Unsigned;
 use hybrid_array::{Array, ArraySize};
 use inout::InOutBuf;
-use subtle::{Choice, ConstantTimeEq};
+use subtle::ConstantTimeEq;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::SessionAead;
-use crate::deck::DeckCore;
+use crate::core_api;
+use crate::deck::{self, DeckCore};
+use crate::params::{self, InvalidParameters, MAX_TAG_SIZE};
+use crate::ratchet::{self, Ratchet};
+use crate::sane::is_empty_ad;
+#[cfg(feature = "alloc")]
+use crate::snapshot::{self, write_state};
+use crate::snapshot::{Header, Mode, SerializeState, SnapshotError};
 
 /// Core parameters used by [`DeckSanse`]
 pub trait DeckSanseCore {
@@ -33,83 +47,337 @@ impl<D: DeckSanseCore> DeckSanse<D> {
     }
 }
 
+impl<D: DeckSanseCore> Ratchet for DeckSanse<D> {
+    fn ratchet(&mut self) {
+        let mut key = ratchet::next_key(&self.d, self.e);
+        *self = Self::init(&key);
+        core_api::wipe(&mut key);
+    }
+}
+
+impl<D: DeckSanseCore + AlgorithmName> DeckSanse<D>
+where
+    D::Core: SerializeState,
+    <D::Core as ExtendableOutputCore>::ReaderCore: Clone,
+{
+    fn snapshot_header() -> Header {
+        Header {
+            mode: Mode::Sanse,
+            tag_size: <D::TagSize as Unsigned>::USIZE,
+            alignment: 0,
+            block_size: <<D::Core as BlockSizeUser>::BlockSize as Unsigned>::USIZE,
+        }
+    }
+
+    /// Export the full session state. See [`snapshot`](crate::snapshot) for the format.
+    ///
+    /// The snapshot contains secret key material, and must be imported at most once: encrypting
+    /// with two sessions imported from the same snapshot, or with this session and one imported
+    /// from its snapshot, reveals whether their messages are equal.
+    #[cfg(feature = "alloc")]
+    pub fn export(&self) -> Vec<u8> {
+        let mut out = Self::snapshot_header().write::<D>();
+        out.push(self.e);
+        write_state(&mut out, &self.d);
+        out
+    }
+
+    /// Import a session exported with [`DeckSanse::export`].
+    ///
+    /// Importing the same snapshot twice and encrypting with both sessions reveals whether their
+    /// messages are equal.
+    pub fn import(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        let mut r = Self::snapshot_header().read::<D>(snapshot)?;
+        let e = r.byte()?;
+        if e > 1 {
+            return Err(SnapshotError::Malformed);
+        }
+        let d = r.state()?;
+        r.finish()?;
+
+        Ok(Self { d, e })
+    }
+
+    /// Export the full session state, sealed under `wrapping_key`.
+    #[cfg(feature = "alloc")]
+    pub fn export_sealed(&self, wrapping_key: &[u8]) -> Vec<u8> {
+        snapshot::seal::<D::Core>(wrapping_key, self.export())
+    }
+
+    /// Import a session exported with [`DeckSanse::export_sealed`].
+    ///
+    /// As with [`DeckSanse::import`], each sealed snapshot must be imported at most once.
+    #[cfg(feature = "alloc")]
+    pub fn import_sealed(wrapping_key: &[u8], sealed: &[u8]) -> Result<Self, SnapshotError> {
+        Self::import(&snapshot::open::<D::Core>(wrapping_key, sealed)?)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<D: DeckSanseCore> Zeroize for DeckSanse<D>
+where
+    D::Core: Zeroize,
+{
+    fn zeroize(&mut self) {
+        self.d.zeroize();
+        self.e.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<D: DeckSanseCore> ZeroizeOnDrop for DeckSanse<D> where D::Core: ZeroizeOnDrop {}
+
 impl<D: DeckSanseCore> SessionAead for DeckSanse<D>
 where
     <D::Core as ExtendableOutputCore>::ReaderCore: Clone,
 {
     type Tag = D::TagSize;
 
-    fn encrypt_inout_detached(
+    fn encrypt_vectored(
         &mut self,
-        ad: &[u8],
+        ad: &[&[u8]],
         buffer: InOutBuf<'_, '_, u8>,
     ) -> Array<u8, Self::Tag> {
-        let e = self.e;
-        self.e ^= 0x01;
+        let mut tag = Array::<u8, D::TagSize>::default();
+        encrypt_message(&mut self.d, &mut self.e, ad, buffer, &mut tag);
+        tag
+    }
 
-        if !ad.is_empty() || buffer.is_empty() {
-            // apply associated data to history
-            let k = apply_padded::<_, 2>(&mut self.d, ad, 0b00 | e);
+    fn decrypt_vectored(
+        &mut self,
+        ad: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &Array<u8, Self::Tag>,
+    ) -> Result<(), Error> {
+        let mut actual_tag = Array::<u8, D::TagSize>::default();
+        decrypt_message(&mut self.d, &mut self.e, ad, buffer, tag, &mut actual_tag)
+    }
+}
 
-            if buffer.is_empty() {
-                return k.read_tag();
+/// Encrypt a message and write its tag, moving the session state forward.
+fn encrypt_message<C: DeckCore + Clone>(
+    d: &mut C,
+    e: &mut u8,
+    ad: &[&[u8]],
+    buffer: InOutBuf<'_, '_, u8>,
+    tag: &mut [u8],
+) {
+    let u = *e;
+    *e ^= 0x01;
+
+    if !is_empty_ad(ad) || buffer.is_empty() {
+        // apply associated data to history
+        let k = apply_ad(d, u, ad);
+
+        if buffer.is_empty() {
+            k.read(tag);
+            return;
+        }
+    }
+
+    let mut d_copy = d.clone();
+
+    // apply plaintext to history
+    apply_padded::<_, 3>(d, buffer.get_in(), 0b010 | u).read(tag);
+
+    // apply tag to history for a SIV keystream and apply keystream to buffer
+    apply_padded::<_, 3>(&mut d_copy, tag, 0b110 | u).xor_in2out(buffer);
+}
+
+/// Decrypt a message, moving the session state forward only if the tag is valid.
+///
+/// `actual_tag` is scratch space of the same length as `tag`.
+fn decrypt_message<C: DeckCore + Clone>(
+    d: &mut C,
+    e: &mut u8,
+    ad: &[&[u8]],
+    buffer: InOutBuf<'_, '_, u8>,
+    tag: &[u8],
+    actual_tag: &mut [u8],
+) -> Result<(), Error>
+where
+    C::ReaderCore: Clone,
+{
+    let u = *e;
+
+    // work on a copy of the history, only committed if the tag is valid
+    let mut next_d = d.clone();
+
+    if !is_empty_ad(ad) || buffer.is_empty() {
+        // apply associated data to history
+        let k = apply_ad(&mut next_d, u, ad);
+
+        if buffer.is_empty() {
+            k.read(actual_tag);
+            if tag.ct_ne(actual_tag).into() {
+                return Err(Error);
             }
+            *d = next_d;
+            *e ^= 0x01;
+            return Ok(());
         }
+    }
 
-        let mut d_copy = self.d.clone();
+    let mut d_copy = next_d.clone();
 
-        // apply plaintext to history
-        let tag = apply_padded::<_, 3>(&mut self.d, buffer.get_in(), 0b010 | e).read_tag();
+    // apply tag to history for a SIV keystream and apply keystream to buffer
+    let k = apply_padded::<_, 3>(&mut d_copy, tag, 0b110 | u);
+    let pt = k.clone().xor_in2out(buffer);
 
-        // apply tag to history for a SIV keystream and apply keystream to buffer
-        apply_padded::<_, 3>(&mut d_copy, &tag, 0b110 | e).xor_in2out(buffer);
+    // apply plaintext to history
+    apply_padded::<_, 3>(&mut next_d, pt, 0b010 | u).read(actual_tag);
 
-        tag
+    if tag.ct_ne(actual_tag).into() {
+        // reapply keystream.
+        let _ct = k.xor_in2out(InOutBuf::from(pt));
+        return Err(Error);
+    }
+
+    *d = next_d;
+    *e ^= 0x01;
+    Ok(())
+}
+
+/// [`DeckSanse`] with the tag size chosen at runtime.
+///
+/// Messages are processed identically to a [`DeckSanse`] with the same tag size.
+pub struct RuntimeDeckSanse<C: DeckCore> {
+    /// the history
+    d: C,
+    /// u1 that is stored in bit 1 (0x01)
+    e: u8,
+    tag_size: usize,
+}
+
+impl<C: DeckCore + Clone> RuntimeDeckSanse<C>
+where
+    C::ReaderCore: Clone,
+{
+    /// Start a session producing `tag_size` byte tags.
+    ///
+    /// The tag size must be between [`MIN_TAG_SIZE`](params::MIN_TAG_SIZE) and [`MAX_TAG_SIZE`].
+    pub fn init(key: &[u8], tag_size: usize) -> Result<Self, InvalidParameters> {
+        params::check_tag_size(tag_size)?;
+        let d = <C as DeckCore>::init(key);
+
+        Ok(Self { d, e: 0, tag_size })
     }
 
-    fn decrypt_inout_detached(
+    /// The size of the tags in bytes.
+    pub fn tag_size(&self) -> usize {
+        self.tag_size
+    }
+
+    /// Encrypt the message in the buffer, writing the tag to `tag`.
+    ///
+    /// # Panics
+    ///
+    /// If `tag` is not [`tag_size`](Self::tag_size) bytes long.
+    pub fn encrypt_inout_detached(
         &mut self,
-        ad: &[u8],
+        associated_data: &[u8],
         buffer: InOutBuf<'_, '_, u8>,
-        tag: &Array<u8, Self::Tag>,
-    ) -> Result<(), Error> {
-        let e = self.e;
-        self.e ^= 0x01;
-
-        if !ad.is_empty() || buffer.is_empty() {
-            // apply associated data to history
-            let k = apply_padded::<_, 2>(&mut self.d, ad, 0b00 | e);
-
-            if buffer.is_empty() {
-                let actual_tag = k.read_tag();
-                if ct_ne(tag, &actual_tag).into() {
-                    return Err(Error);
-                }
-                return Ok(());
-            }
-        }
-
-        let mut d_copy = self.d.clone();
+        tag: &mut [u8],
+    ) {
+        self.encrypt_vectored(&[associated_data], buffer, tag)
+    }
 
-        // apply tag to history for a SIV keystream and apply keystream to buffer
-        let k = apply_padded::<_, 3>(&mut d_copy, &tag, 0b110 | e);
-        let pt = k.clone().xor_in2out(buffer);
+    /// Decrypt the message in the buffer, returning an error if the tag is invalid.
+    pub fn decrypt_inout_detached(
+        &mut self,
+        associated_data: &[u8],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        self.decrypt_vectored(&[associated_data], buffer, tag)
+    }
 
-        // apply plaintext to history
-        let actual_tag = apply_padded::<_, 3>(&mut self.d, &pt, 0b010 | e).read_tag();
+    /// Like [`RuntimeDeckSanse::encrypt_inout_detached`], with the associated data split into
+    /// several fields. See [`SessionAead::encrypt_vectored`].
+    ///
+    /// # Panics
+    ///
+    /// If `tag` is not [`tag_size`](Self::tag_size) bytes long.
+    pub fn encrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &mut [u8],
+    ) {
+        assert_eq!(tag.len(), self.tag_size, "tag length mismatch");
+        encrypt_message(&mut self.d, &mut self.e, associated_data, buffer, tag);
+    }
 
-        if ct_ne(tag, &actual_tag).into() {
-            // reapply keystream.
-            let _ct = k.xor_in2out(InOutBuf::from(pt));
+    /// Like [`RuntimeDeckSanse::decrypt_inout_detached`], with the associated data split into
+    /// several fields. See [`SessionAead::encrypt_vectored`].
+    pub fn decrypt_vectored(
+        &mut self,
+        associated_data: &[&[u8]],
+        buffer: InOutBuf<'_, '_, u8>,
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        if tag.len() != self.tag_size {
             return Err(Error);
         }
+        let mut actual_tag = [0; MAX_TAG_SIZE];
+        decrypt_message(
+            &mut self.d,
+            &mut self.e,
+            associated_data,
+            buffer,
+            tag,
+            &mut actual_tag[..self.tag_size],
+        )
+    }
+
+    /// Encrypt the message in the buffer in-place, appending the tag.
+    /// See [`SessionAead::encrypt_in_place`].
+    pub fn encrypt_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut impl aead::Buffer,
+    ) -> aead::Result<()> {
+        params::encrypt_in_place(buffer, self.tag_size, |msg, tag| {
+            self.encrypt_inout_detached(associated_data, msg, tag)
+        })
+    }
+
+    /// Decrypt the message in the buffer in-place, checking the appended tag.
+    /// See [`SessionAead::decrypt_in_place`].
+    pub fn decrypt_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut impl aead::Buffer,
+    ) -> aead::Result<()> {
+        params::decrypt_in_place(buffer, self.tag_size, |msg, tag| {
+            self.decrypt_inout_detached(associated_data, msg, tag)
+        })
+    }
+}
+
+impl<C: DeckCore + Clone> Ratchet for RuntimeDeckSanse<C> {
+    fn ratchet(&mut self) {
+        let mut key = ratchet::next_key(&self.d, self.e);
+        self.d = <C as DeckCore>::init(&key);
+        self.e = 0;
+        core_api::wipe(&mut key);
+    }
+}
 
-        Ok(())
+#[cfg(feature = "zeroize")]
+impl<C: DeckCore + Zeroize> Zeroize for RuntimeDeckSanse<C> {
+    fn zeroize(&mut self) {
+        self.d.zeroize();
+        self.e.zeroize();
     }
 }
 
-fn ct_ne<T: ArraySize>(a: &Array<u8, T>, b: &Array<u8, T>) -> Choice {
-    a.ct_ne(b)
+#[cfg(feature = "zeroize")]
+impl<C: DeckCore + ZeroizeOnDrop> ZeroizeOnDrop for RuntimeDeckSanse<C> {}
+
+/// Apply the associated data to the history, see [`deck::apply_ad`].
+fn apply_ad<D: DeckCore>(d: &mut D, e: u8, ad: &[&[u8]]) -> KeyStream<D::ReaderCore> {
+    KeyStream(deck::apply_ad(d, e, ad))
 }
 
 #[inline(always)]
@@ -120,7 +388,17 @@ pub(crate) fn apply_padded<D: DeckCore, const BITS: u8>(
 ) -> KeyStream<D::ReaderCore> {
     let mut buffer = Buffer::<D>::new(&[]);
     buffer.digest_blocks(m, |b| d.update_blocks(b));
-    KeyStream(d.finalize_deck_prepadded::<BITS>(&mut buffer, b))
+    finalize_padded::<D, BITS>(d, &mut buffer, b)
+}
+
+/// Like [`apply_padded`], for a string already absorbed into `d` and `buffer`.
+#[inline(always)]
+pub(crate) fn finalize_padded<D: DeckCore, const BITS: u8>(
+    d: &mut D,
+    buffer: &mut Buffer<D>,
+    b: u8,
+) -> KeyStream<D::ReaderCore> {
+    KeyStream(d.finalize_deck_prepadded::<BITS>(buffer, b))
 }
 
 #[derive(Clone, Default)]
@@ -140,18 +418,24 @@ impl<X: XofReaderCore> KeyStream<X> {
         }
         let n = last.len();
         if n > 0 {
-            last.copy_from_slice(&self.0.read_block()[..n]);
+            let mut k = self.0.read_block();
+            last.copy_from_slice(&k[..n]);
+            core_api::wipe_block(&mut k);
         }
     }
 
     pub(crate) fn xor_in2out<'out>(mut self, mut buf: InOutBuf<'_, 'out, u8>) -> &'out mut [u8] {
         let (blocks, mut last) = buf.reborrow().into_chunks();
         for mut block in blocks {
-            block.xor_in2out(&self.0.read_block());
+            let mut k = self.0.read_block();
+            block.xor_in2out(&k);
+            core_api::wipe_block(&mut k);
         }
         let n = last.len();
         if n > 0 {
-            last.xor_in2out(&self.0.read_block()[..n]);
+            let mut k = self.0.read_block();
+            last.xor_in2out(&k[..n]);
+            core_api::wipe_block(&mut k);
         }
         buf.into_out()
     }
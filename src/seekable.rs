@@ -0,0 +1,426 @@
+//! Random-access authenticated encryption for large objects.
+//!
+//! [`SeekableWriter`] splits an object into fixed-size segments and seals each on its own, so
+//! [`SeekableReader`] can decrypt any byte range by reading and verifying only the segments
+//! that overlap it. A sealed object is:
+//!
+//! | field        | size          | contents                                       |
+//! |--------------|---------------|------------------------------------------------|
+//! | magic        | 4             | [`MAGIC`]                                      |
+//! | version      | 1             | [`VERSION`]                                    |
+//! | segment size | 4             | plaintext length of each segment as `u32` LE   |
+//! | nonce        | [`NONCE_LEN`] | unique per key                                 |
+//! | segments     | variable      | ciphertext followed by tag, per segment        |
+//! | commitment   | `D::TagSize`  | commitment to the header and all segment tags  |
+//!
+//! The header is applied to the keyed deck first, so everything below is bound to it. The
+//! keystream of segment `i` is the output of the history (header, `i`), and its tag is the
+//! output after the ciphertext is applied on top. Tags are therefore bound to the index of their
+//! segment, so segments cannot be reordered, and the last segment, which is always shorter than
+//! the segment size and possibly empty, is applied with a different frame, so the object cannot
+//! be truncated. The commitment is the output of the history (header, all segment tags) and
+//! authenticates the object as a whole; [`SeekableWriter::finish`] returns it, for example to
+//! use as an identifier of the object, and [`SeekableReader::verify_commitment`] checks it.
+
+use alloc::vec::Vec;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use digest::core_api::Buffer;
+use digest::typenum::Unsigned;
+use hybrid_array::{Array, ArraySize};
+use inout::InOutBuf;
+use subtle::ConstantTimeEq;
+
+use crate::deck::DeckCore;
+use crate::sanse::{apply_padded, finalize_padded};
+
+/// Core parameters used by [`SeekableWriter`] and [`SeekableReader`]
+pub trait DeckSeekableCore {
+    type Core: DeckCore + Clone;
+    type TagSize: ArraySize;
+}
+
+/// Magic bytes at the start of every sealed object.
+pub const MAGIC: [u8; 4] = *b"FRFS";
+
+/// Version of the format described in the [module documentation](self).
+pub const VERSION: u8 = 1;
+
+/// Length of the nonce in the header.
+pub const NONCE_LEN: usize = 16;
+
+/// Length of the header.
+pub const HEADER_LEN: usize = 9 + NONCE_LEN;
+
+/// Default plaintext length of each segment.
+pub const DEFAULT_SEGMENT_SIZE: usize = 64 * 1024;
+
+/// Largest segment size accepted by [`SeekableReader`].
+pub const MAX_SEGMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Frames applied to the histories of an object.
+const FRAME_HEADER: u8 = 0b000;
+const FRAME_INDEX: u8 = 0b001;
+const FRAME_SEGMENT: u8 = 0b010;
+const FRAME_LAST_SEGMENT: u8 = 0b011;
+const FRAME_COMMITMENT: u8 = 0b100;
+
+fn encode_header(segment_size: u32, nonce: &[u8; NONCE_LEN]) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4] = VERSION;
+    header[5..9].copy_from_slice(&segment_size.to_le_bytes());
+    header[9..].copy_from_slice(nonce);
+    header
+}
+
+/// The keyed deck with the header applied, shared by every segment.
+fn object_base<C: DeckCore + Clone>(key: &[u8], header: &[u8; HEADER_LEN]) -> C {
+    let mut d = C::init(key);
+    apply_padded::<_, 3>(&mut d, header, FRAME_HEADER);
+    d
+}
+
+/// Encrypt segment `index` in the buffer, returning its tag.
+fn seal_segment<C: DeckCore + Clone, T: ArraySize>(
+    base: &C,
+    index: u64,
+    last: bool,
+    buffer: InOutBuf<'_, '_, u8>,
+) -> Array<u8, T> {
+    let mut d = base.clone();
+    let k = apply_padded::<_, 3>(&mut d, &index.to_le_bytes(), FRAME_INDEX);
+    let ciphertext = k.xor_in2out(buffer);
+    let frame = if last {
+        FRAME_LAST_SEGMENT
+    } else {
+        FRAME_SEGMENT
+    };
+    apply_padded::<_, 3>(&mut d, ciphertext, frame).read_tag()
+}
+
+/// Verify and decrypt segment `index` in place. The buffer is left untouched on failure.
+fn open_segment<C: DeckCore + Clone, T: ArraySize>(
+    base: &C,
+    index: u64,
+    last: bool,
+    buffer: &mut [u8],
+    tag: &Array<u8, T>,
+) -> aead::Result<()> {
+    // the keystream only depends on the index, so the tag can be checked first
+    let mut d = base.clone();
+    let k = apply_padded::<_, 3>(&mut d, &index.to_le_bytes(), FRAME_INDEX);
+    let frame = if last {
+        FRAME_LAST_SEGMENT
+    } else {
+        FRAME_SEGMENT
+    };
+    let actual_tag = apply_padded::<_, 3>(&mut d, buffer, frame).read_tag::<T>();
+    if tag.ct_ne(&actual_tag).into() {
+        return Err(aead::Error);
+    }
+    k.xor_in2out(InOutBuf::from(buffer));
+    Ok(())
+}
+
+/// Accumulates the segment tags into the commitment.
+struct Commitment<C: DeckCore> {
+    d: C,
+    buffer: Buffer<C>,
+}
+
+impl<C: DeckCore + Clone> Commitment<C> {
+    fn new(base: &C) -> Self {
+        Self {
+            d: base.clone(),
+            buffer: Buffer::<C>::new(&[]),
+        }
+    }
+
+    fn absorb(&mut self, tag: &[u8]) {
+        let Self { d, buffer } = self;
+        buffer.digest_blocks(tag, |b| d.update_blocks(b));
+    }
+
+    fn finish<T: ArraySize>(mut self) -> Array<u8, T> {
+        finalize_padded::<_, 3>(&mut self.d, &mut self.buffer, FRAME_COMMITMENT).read_tag()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("sealed object failed earlier")
+}
+
+/// Writes a sealed object to the inner writer.
+///
+/// [`SeekableWriter::finish`] must be called to write the last segment and the commitment,
+/// otherwise the object cannot be read.
+pub struct SeekableWriter<W: Write, D: DeckSeekableCore> {
+    inner: W,
+    base: D::Core,
+    commitment: Commitment<D::Core>,
+    /// plaintext of the segment being filled
+    buffer: Vec<u8>,
+    segment_size: usize,
+    next_index: u64,
+    poisoned: bool,
+}
+
+impl<W: Write, D: DeckSeekableCore> SeekableWriter<W, D> {
+    /// Write the header. The nonce must never be reused with the same key.
+    pub fn new(inner: W, key: &[u8], nonce: &[u8; NONCE_LEN]) -> io::Result<Self> {
+        Self::with_segment_size(inner, key, nonce, DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// # Panics
+    ///
+    /// If `segment_size` is zero or larger than [`MAX_SEGMENT_SIZE`].
+    pub fn with_segment_size(
+        mut inner: W,
+        key: &[u8],
+        nonce: &[u8; NONCE_LEN],
+        segment_size: usize,
+    ) -> io::Result<Self> {
+        assert!((1..=MAX_SEGMENT_SIZE).contains(&segment_size));
+
+        let header = encode_header(segment_size as u32, nonce);
+        inner.write_all(&header)?;
+
+        let base = object_base::<D::Core>(key, &header);
+        Ok(Self {
+            inner,
+            commitment: Commitment::new(&base),
+            base,
+            buffer: Vec::with_capacity(segment_size),
+            segment_size,
+            next_index: 0,
+            poisoned: false,
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Seal and write the buffered data as one segment.
+    fn write_segment(&mut self, last: bool) -> io::Result<()> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        // the commitment moves forward here, so a failed write breaks the object
+        self.poisoned = true;
+
+        let tag = seal_segment::<_, D::TagSize>(
+            &self.base,
+            self.next_index,
+            last,
+            InOutBuf::from(&mut self.buffer[..]),
+        );
+        self.commitment.absorb(&tag);
+        self.next_index += 1;
+
+        self.inner.write_all(&self.buffer)?;
+        self.inner.write_all(&tag)?;
+        self.buffer.clear();
+
+        self.poisoned = false;
+        Ok(())
+    }
+
+    /// Write the last segment and the commitment and flush, returning the inner writer and the
+    /// commitment.
+    pub fn finish(mut self) -> io::Result<(W, Array<u8, D::TagSize>)> {
+        self.write_segment(true)?;
+        let commitment = self.commitment.finish::<D::TagSize>();
+        self.inner.write_all(&commitment)?;
+        self.inner.flush()?;
+        Ok((self.inner, commitment))
+    }
+}
+
+impl<W: Write, D: DeckSeekableCore> Write for SeekableWriter<W, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.poisoned {
+            return Err(poisoned());
+        }
+        let n = buf.len().min(self.segment_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == self.segment_size {
+            self.write_segment(false)?;
+        }
+        Ok(n)
+    }
+
+    /// Flush the inner writer. Buffered data is only written once its segment is complete.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts any range of a sealed object, verifying only the segments it reads.
+///
+/// Plaintext is only returned once the segment containing it has been authenticated, and the end
+/// of the object is only reported once the last segment, even an empty one, has been
+/// authenticated. A segment that fails authentication is reported as
+/// [`io::ErrorKind::InvalidData`] and leaves the reader usable for the other segments.
+pub struct SeekableReader<R: Read + Seek, D: DeckSeekableCore> {
+    inner: R,
+    base: D::Core,
+    segment_size: u64,
+    /// number of segments, including the possibly empty last one
+    segments: u64,
+    /// plaintext length of the object
+    len: u64,
+    /// plaintext position
+    pos: u64,
+    /// index and plaintext of the last segment read
+    cached: Option<(u64, Vec<u8>)>,
+}
+
+impl<R: Read + Seek, D: DeckSeekableCore> SeekableReader<R, D> {
+    /// Read the header and work out the layout of the object from its length.
+    pub fn new(mut inner: R, key: &[u8]) -> io::Result<Self> {
+        let tag_size = D::TagSize::U64;
+
+        inner.seek(SeekFrom::Start(0))?;
+        let mut header = [0; HEADER_LEN];
+        inner.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(invalid_data("not a sealed object"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid_data("unsupported sealed object version"));
+        }
+        let segment_size = u32::from_le_bytes(header[5..9].try_into().unwrap()) as u64;
+        if segment_size == 0 || segment_size > MAX_SEGMENT_SIZE as u64 {
+            return Err(invalid_data("invalid segment size"));
+        }
+
+        let total = inner.seek(SeekFrom::End(0))?;
+        let body = total
+            .checked_sub(HEADER_LEN as u64 + tag_size)
+            .ok_or_else(|| invalid_data("sealed object truncated"))?;
+        let record = segment_size + tag_size;
+        let full = body / record;
+        let last_len = (body % record)
+            .checked_sub(tag_size)
+            .ok_or_else(|| invalid_data("sealed object truncated"))?;
+
+        Ok(Self {
+            inner,
+            base: object_base(key, &header),
+            segment_size,
+            segments: full + 1,
+            len: full * segment_size + last_len,
+            pos: 0,
+            cached: None,
+        })
+    }
+
+    /// The plaintext length of the object.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Offset of segment `index` in the sealed object.
+    fn segment_offset(&self, index: u64) -> u64 {
+        HEADER_LEN as u64 + index * (self.segment_size + D::TagSize::U64)
+    }
+
+    /// Plaintext length of segment `index`.
+    fn segment_len(&self, index: u64) -> usize {
+        (self.len - index * self.segment_size).min(self.segment_size) as usize
+    }
+
+    /// Read and verify the commitment, reading every segment tag but no ciphertext.
+    pub fn verify_commitment(&mut self) -> io::Result<()> {
+        let mut commitment = Commitment::new(&self.base);
+        let mut tag = Array::<u8, D::TagSize>::default();
+        for index in 0..self.segments {
+            let offset = self.segment_offset(index) + self.segment_len(index) as u64;
+            self.inner.seek(SeekFrom::Start(offset))?;
+            self.inner.read_exact(&mut tag)?;
+            commitment.absorb(&tag);
+        }
+
+        // the commitment directly follows the last tag
+        self.inner.read_exact(&mut tag)?;
+        if tag.ct_ne(&commitment.finish::<D::TagSize>()).into() {
+            return Err(invalid_data("sealed object commitment mismatch"));
+        }
+        Ok(())
+    }
+
+    /// Read, verify and decrypt segment `index` into the cache.
+    fn load_segment(&mut self, index: u64) -> io::Result<&[u8]> {
+        if !matches!(&self.cached, Some((i, _)) if *i == index) {
+            let len = self.segment_len(index);
+            let mut buffer = self.cached.take().map(|(_, b)| b).unwrap_or_default();
+            buffer.resize(len, 0);
+            let mut tag = Array::<u8, D::TagSize>::default();
+
+            self.inner
+                .seek(SeekFrom::Start(self.segment_offset(index)))?;
+            self.inner.read_exact(&mut buffer)?;
+            self.inner.read_exact(&mut tag)?;
+
+            let last = index == self.segments - 1;
+            if open_segment(&self.base, index, last, &mut buffer, &tag).is_err() {
+                return Err(invalid_data("sealed object segment failed authentication"));
+            }
+            self.cached = Some((index, buffer));
+        }
+        Ok(&self.cached.as_ref().unwrap().1)
+    }
+}
+
+impl<R: Read + Seek, D: DeckSeekableCore> Read for SeekableReader<R, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos >= self.len {
+            // an object cut at a segment boundary only shows in its last segment
+            self.load_segment(self.segments - 1)?;
+            return Ok(0);
+        }
+        let index = self.pos / self.segment_size;
+        let start = (self.pos % self.segment_size) as usize;
+        let segment = self.load_segment(index)?;
+
+        let n = buf.len().min(segment.len() - start);
+        buf[..n].copy_from_slice(&segment[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek, D: DeckSeekableCore> Seek for SeekableReader<R, D> {
+    /// Seek in the plaintext. Seeking past the end is allowed, and reads there return nothing.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,280 @@
+//! Session snapshots.
+//!
+//! [`DeckSane`](crate::sane::DeckSane) and [`DeckSanse`](crate::sanse::DeckSanse) sessions can be
+//! exported to bytes and later imported again, for example to move a session between processes.
+//! A snapshot contains the full secret session state, so it should either be stored securely or
+//! sealed under a wrapping key with the `*_sealed` variants.
+//!
+//! A snapshot must be imported at most once. Each import is an independent copy of the session,
+//! and the copies produce the same output: two copies of a Deck-SANE session encrypt their next
+//! messages with the same keystream, revealing the XOR of the plaintexts, and two copies of a
+//! Deck-SANSE session reveal whether their next messages are equal. The same holds for a session
+//! that keeps encrypting after it was exported. Nothing in a snapshot can detect a second import,
+//! so callers that may restore the same snapshot twice should bind each import to state that only
+//! moves forward, for example by keeping a counter next to the snapshot, incrementing it on every
+//! import and sending an empty message with the counter as associated data before any other.
+//!
+//! The snapshot format is:
+//!
+//! | field       | size     | contents                                        |
+//! |-------------|----------|-------------------------------------------------|
+//! | magic       | 4        | `FFSS`                                          |
+//! | version     | 1        | [`VERSION`]                                     |
+//! | mode        | 1        | `1` for Deck-SANE, `2` for Deck-SANSE           |
+//! | name length | 1        | length of the algorithm name                    |
+//! | name        | variable | [`AlgorithmName`] of the session parameters     |
+//! | parameters  | 6        | tag size, alignment and block size as `u16` LE  |
+//! | state       | variable | mode specific session state                     |
+
+#[cfg(feature = "alloc")]
+use alloc::{string::String, string::ToString, vec::Vec};
+use core::fmt;
+use core::marker::PhantomData;
+
+use cipher::AlgorithmName;
+#[cfg(feature = "alloc")]
+use digest::{consts::U32, core_api::ExtendableOutputCore, typenum::Unsigned};
+#[cfg(feature = "alloc")]
+use inout::InOutBuf;
+
+#[cfg(feature = "alloc")]
+use crate::{
+    SessionAead,
+    deck::DeckCore,
+    sanse::{DeckSanse, DeckSanseCore},
+};
+
+/// Current version of the snapshot format.
+pub const VERSION: u8 = 1;
+
+const MAGIC: &[u8; 4] = b"FFSS";
+
+/// Associated data used when sealing a snapshot.
+#[cfg(feature = "alloc")]
+const SEAL_AD: &[u8] = b"farfalle session snapshot";
+
+/// Deck state that can be exported to and restored from bytes.
+pub trait SerializeState: Sized {
+    /// Length of the serialized state in bytes
+    const STATE_LEN: usize;
+
+    /// Write the state into `out`, which is exactly [`STATE_LEN`](SerializeState::STATE_LEN) bytes long.
+    fn serialize_state(&self, out: &mut [u8]);
+
+    /// Restore the state from `state`, which is exactly [`STATE_LEN`](SerializeState::STATE_LEN) bytes long.
+    fn deserialize_state(state: &[u8]) -> Self;
+}
+
+/// Error returned when importing a session snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The snapshot is truncated or otherwise malformed
+    Malformed,
+    /// The snapshot was written by an unsupported version of the format
+    UnsupportedVersion,
+    /// The snapshot is for a different mode, algorithm or set of parameters
+    AlgorithmMismatch,
+    /// The sealed snapshot failed authentication
+    Unauthentic,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SnapshotError::Malformed => "malformed session snapshot",
+            SnapshotError::UnsupportedVersion => "unsupported session snapshot version",
+            SnapshotError::AlgorithmMismatch => "session snapshot is for a different algorithm",
+            SnapshotError::Unauthentic => "sealed session snapshot failed authentication",
+        })
+    }
+}
+
+impl core::error::Error for SnapshotError {}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Sane = 1,
+    Sanse = 2,
+}
+
+/// Mode and parameters that identify the session a snapshot belongs to.
+pub(crate) struct Header {
+    pub(crate) mode: Mode,
+    pub(crate) tag_size: usize,
+    pub(crate) alignment: usize,
+    pub(crate) block_size: usize,
+}
+
+struct Name<A>(PhantomData<A>);
+
+impl<A: AlgorithmName> fmt::Display for Name<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        A::write_alg_name(f)
+    }
+}
+
+fn param(n: usize) -> [u8; 2] {
+    u16::try_from(n)
+        .expect("session parameters must fit in a u16")
+        .to_le_bytes()
+}
+
+#[cfg(feature = "alloc")]
+fn name<A: AlgorithmName>() -> String {
+    Name::<A>(PhantomData).to_string()
+}
+
+/// Whether `name` is the [`AlgorithmName`] of `A`, without allocating.
+fn name_matches<A: AlgorithmName>(name: &[u8]) -> bool {
+    /// Consumes the expected name as the actual one is written.
+    struct Matcher<'a>(&'a [u8]);
+
+    impl fmt::Write for Matcher<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 = self.0.strip_prefix(s.as_bytes()).ok_or(fmt::Error)?;
+            Ok(())
+        }
+    }
+
+    let mut matcher = Matcher(name);
+    fmt::write(&mut matcher, format_args!("{}", Name::<A>(PhantomData))).is_ok()
+        && matcher.0.is_empty()
+}
+
+impl Header {
+    /// Start a snapshot by writing the header.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn write<A: AlgorithmName>(&self) -> Vec<u8> {
+        let name = name::<A>();
+        let name_len = u8::try_from(name.len()).expect("algorithm name must fit in 255 bytes");
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(self.mode as u8);
+        out.push(name_len);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&param(self.tag_size));
+        out.extend_from_slice(&param(self.alignment));
+        out.extend_from_slice(&param(self.block_size));
+        out
+    }
+
+    /// Check the header of a snapshot, returning the remaining state.
+    pub(crate) fn read<'a, A: AlgorithmName>(
+        &self,
+        snapshot: &'a [u8],
+    ) -> Result<Reader<'a>, SnapshotError> {
+        let mut r = Reader(snapshot);
+        if r.take(4)? != MAGIC {
+            return Err(SnapshotError::Malformed);
+        }
+        if r.byte()? != VERSION {
+            return Err(SnapshotError::UnsupportedVersion);
+        }
+        let mode = r.byte()?;
+        let name_len = r.byte()?;
+        let name = r.take(name_len as usize)?;
+        let params = r.take(6)?;
+
+        let mut expected = [0; 6];
+        expected[0..2].copy_from_slice(&param(self.tag_size));
+        expected[2..4].copy_from_slice(&param(self.alignment));
+        expected[4..6].copy_from_slice(&param(self.block_size));
+
+        if mode != self.mode as u8 || !name_matches::<A>(name) || params != expected {
+            return Err(SnapshotError::AlgorithmMismatch);
+        }
+
+        Ok(r)
+    }
+}
+
+/// Cursor over the state in a snapshot.
+pub(crate) struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.0.len() < n {
+            return Err(SnapshotError::Malformed);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn state<S: SerializeState>(&mut self) -> Result<S, SnapshotError> {
+        Ok(S::deserialize_state(self.take(S::STATE_LEN)?))
+    }
+
+    /// Require that the whole snapshot has been read.
+    pub(crate) fn finish(self) -> Result<(), SnapshotError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(SnapshotError::Malformed)
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub(crate) fn write_state<S: SerializeState>(out: &mut Vec<u8>, state: &S) {
+    let n = out.len();
+    out.resize(n + S::STATE_LEN, 0);
+    state.serialize_state(&mut out[n..]);
+}
+
+/// Parameters for sealing snapshots with Deck-SANSE under the wrapping key.
+#[cfg(feature = "alloc")]
+struct Seal<C>(PhantomData<C>);
+
+#[cfg(feature = "alloc")]
+impl<C: DeckCore + Clone> DeckSanseCore for Seal<C> {
+    type Core = C;
+    type TagSize = SealTag;
+}
+
+#[cfg(feature = "alloc")]
+type SealTag = U32;
+
+/// Seal a snapshot under the wrapping key, appending the tag.
+#[cfg(feature = "alloc")]
+pub(crate) fn seal<C>(wrapping_key: &[u8], mut snapshot: Vec<u8>) -> Vec<u8>
+where
+    C: DeckCore + Clone,
+    <C as ExtendableOutputCore>::ReaderCore: Clone,
+{
+    let mut sanse = DeckSanse::<Seal<C>>::init(wrapping_key);
+    let tag = sanse.encrypt_inout_detached(SEAL_AD, InOutBuf::from(&mut snapshot[..]));
+    snapshot.extend_from_slice(&tag);
+    snapshot
+}
+
+/// Open a snapshot sealed with [`seal`].
+#[cfg(feature = "alloc")]
+pub(crate) fn open<C>(wrapping_key: &[u8], sealed: &[u8]) -> Result<Vec<u8>, SnapshotError>
+where
+    C: DeckCore + Clone,
+    <C as ExtendableOutputCore>::ReaderCore: Clone,
+{
+    let n = sealed
+        .len()
+        .checked_sub(<SealTag as Unsigned>::USIZE)
+        .ok_or(SnapshotError::Malformed)?;
+    let (ciphertext, tag) = sealed.split_at(n);
+
+    let mut snapshot = ciphertext.to_vec();
+    let mut sanse = DeckSanse::<Seal<C>>::init(wrapping_key);
+    sanse
+        .decrypt_inout_detached(
+            SEAL_AD,
+            InOutBuf::from(&mut snapshot[..]),
+            tag.try_into().map_err(|_| SnapshotError::Malformed)?,
+        )
+        .map_err(|_| SnapshotError::Unauthentic)?;
+    Ok(snapshot)
+}
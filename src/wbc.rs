@@ -1,14 +1,21 @@
 //! Wide block cipher
 
-use std::marker::PhantomData;
+use core::marker::PhantomData;
 
-use cipher::{Array, InOutBuf, typenum::Unsigned};
+use cipher::{InOutBuf, typenum::Unsigned};
 use crypto_common::BlockSizes;
 use digest::core_api::{Buffer, ExtendableOutputCore, UpdateCore};
 use hybrid_array::ArraySize;
-use subtle::ConstantTimeEq;
+use subtle::{Choice, ConstantTimeEq};
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
-use crate::{deck::DeckCore, sanse::apply_padded};
+use crate::{
+    core_api,
+    deck::DeckCore,
+    params::{self, InvalidParameters},
+    sanse::apply_padded,
+};
 
 pub trait WideBlockCipherCore {
     type BlockSize: BlockSizes;
@@ -20,6 +27,8 @@ pub trait WideBlockCipherCore {
 pub struct WideBlockCipher<Core: WideBlockCipherCore> {
     g: Core::G,
     h: Core::H,
+    /// the alignment of the split between the left and right parts
+    alignment: usize,
 }
 
 impl<Core: WideBlockCipherCore> Clone for WideBlockCipher<Core> {
@@ -27,46 +36,85 @@ impl<Core: WideBlockCipherCore> Clone for WideBlockCipher<Core> {
         Self {
             g: self.g.clone(),
             h: self.h.clone(),
+            alignment: self.alignment,
         }
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl<Core: WideBlockCipherCore> Zeroize for WideBlockCipher<Core>
+where
+    Core::G: Zeroize,
+    Core::H: Zeroize,
+{
+    fn zeroize(&mut self) {
+        self.g.zeroize();
+        self.h.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Core: WideBlockCipherCore> ZeroizeOnDrop for WideBlockCipher<Core>
+where
+    Core::G: ZeroizeOnDrop,
+    Core::H: ZeroizeOnDrop,
+{
+}
+
 impl<Core: WideBlockCipherCore> WideBlockCipher<Core> {
+    /// # Panics
+    ///
+    /// If `Core::Alignnemt` is not a valid alignment, see [`WideBlockCipher::with_alignment`].
     pub fn init(key: &[u8]) -> Self {
+        Self::with_alignment(key, <Core::Alignnemt as Unsigned>::USIZE)
+            .expect("invalid wide block cipher alignment")
+    }
+
+    /// Like [`WideBlockCipher::init`], with the alignment chosen at runtime instead of
+    /// `Core::Alignnemt`.
+    ///
+    /// The alignment must be between 2 and [`MAX_ALIGNMENT`](params::MAX_ALIGNMENT) and divide
+    /// `Core::BlockSize`.
+    pub fn with_alignment(key: &[u8], alignment: usize) -> Result<Self, InvalidParameters> {
+        params::check_alignment(alignment)?;
+        let b = <Core::BlockSize as Unsigned>::USIZE;
+        if alignment < 2 || b % alignment != 0 {
+            return Err(InvalidParameters);
+        }
+
         let g = <Core::G as DeckCore>::init(key);
         let h = <Core::H as DeckCore>::init(key);
-        Self { g, h }
+        Ok(Self { g, h, alignment })
     }
 }
 
-fn split<Core: WideBlockCipherCore>(n: usize) -> usize {
-    let l = <Core::Alignnemt as Unsigned>::USIZE;
-    let b = <Core::BlockSize as Unsigned>::USIZE;
-    assert!(b % l == 0);
-    assert!(l >= 2);
-
+/// The length of the left part, for an alignment `l` and block size `b`.
+pub(crate) fn split(n: usize, l: usize, b: usize) -> usize {
     if n <= 2 * b - (l + 2) {
         ((n + l) / (2 * l)) * l
     } else {
         let q = (n + l + 1).div_ceil(b);
         let x = (q - 1).ilog2();
-        (q - 1 << x) * b - l
+        (q - (1 << x)) * b - l
     }
 }
 
 impl<Core: WideBlockCipherCore> WideBlockCipher<Core> {
     pub fn encrypt_inout(self, tweak: &[u8], buffer: InOutBuf<'_, '_, u8>) {
-        let Self { mut g, mut h } = self;
+        let Self {
+            mut g,
+            mut h,
+            alignment,
+        } = self;
 
         let mut tweak_buffer = Buffer::<Core::G>::new(&[]);
         tweak_buffer.digest_blocks(tweak, |t| g.update_blocks(t));
         g.finalize_xof_core(&mut tweak_buffer);
 
         let n = buffer.len();
-        let s = split::<Core>(n);
-        let (left, right) = buffer.into_out_with_copied_in().split_at_mut(s);
-
         let b = <Core::BlockSize as Unsigned>::USIZE;
+        let s = split(n, alignment, b);
+        let (left, right) = buffer.into_out_with_copied_in().split_at_mut(s);
 
         let (right0, _) = right.split_at_mut(core::cmp::min(b, n - s));
         apply_padded::<_, 1>(&mut h.clone(), left, 0b0).xor_in2out(InOutBuf::from(right0));
@@ -79,17 +127,20 @@ impl<Core: WideBlockCipherCore> WideBlockCipher<Core> {
     }
 
     pub fn decrypt_inout(self, tweak: &[u8], buffer: InOutBuf<'_, '_, u8>) {
-        let Self { mut g, mut h } = self;
+        let Self {
+            mut g,
+            mut h,
+            alignment,
+        } = self;
 
         let mut tweak_buffer = Buffer::<Core::G>::new(&[]);
         tweak_buffer.digest_blocks(tweak, |t| g.update_blocks(t));
         g.finalize_xof_core(&mut tweak_buffer);
 
         let n = buffer.len();
-        let s = split::<Core>(n);
-        let (left, right) = buffer.into_out_with_copied_in().split_at_mut(s);
-
         let b = <Core::BlockSize as Unsigned>::USIZE;
+        let s = split(n, alignment, b);
+        let (left, right) = buffer.into_out_with_copied_in().split_at_mut(s);
 
         let (left0, _) = left.split_at_mut(core::cmp::min(b, s));
         apply_padded::<_, 1>(&mut h.clone(), right, 0b1).xor_in2out(InOutBuf::from(left0));
@@ -102,6 +153,163 @@ impl<Core: WideBlockCipherCore> WideBlockCipher<Core> {
     }
 }
 
+/// Redundancy in the plaintext that authenticates a [`WideBlockCipher`] ciphertext.
+///
+/// This is the encode-then-encipher approach to authenticated encryption: the plaintext is
+/// extended with [`expansion`](Redundancy::expansion) zero bytes before enciphering, and after
+/// deciphering both those zero bytes and any redundancy already present in the plaintext are
+/// verified. Any modification of the ciphertext garbles the whole plaintext, so the strength of
+/// the authentication is the total number of redundant bits.
+pub trait Redundancy {
+    /// Number of zero bytes appended to the plaintext before it is enciphered.
+    fn expansion(&self) -> usize;
+
+    /// Verify the redundancy present in the deciphered plaintext, excluding the expansion bytes.
+    ///
+    /// This should run in constant time, as the plaintext is not yet authenticated.
+    fn verify(&self, plaintext: &[u8]) -> Choice;
+}
+
+/// Authenticate using only a runtime-configured number of zero bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct ZeroExpansion(usize);
+
+impl ZeroExpansion {
+    /// Extend the plaintext with `expansion` zero bytes, which must be at least
+    /// [`MIN_TAG_SIZE`](params::MIN_TAG_SIZE) as they are the only authentication.
+    pub fn new(expansion: usize) -> Result<Self, InvalidParameters> {
+        if expansion < params::MIN_TAG_SIZE {
+            return Err(InvalidParameters);
+        }
+        Ok(Self(expansion))
+    }
+}
+
+impl Redundancy for ZeroExpansion {
+    fn expansion(&self) -> usize {
+        self.0
+    }
+
+    fn verify(&self, _plaintext: &[u8]) -> Choice {
+        Choice::from(1)
+    }
+}
+
+/// Authenticate using redundancy already present in the plaintext, such as a protocol header
+/// or a checksum, checked by `verify`, plus `expansion` zero bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct Verified<F> {
+    pub expansion: usize,
+    pub verify: F,
+}
+
+impl<F: Fn(&[u8]) -> Choice> Redundancy for Verified<F> {
+    fn expansion(&self) -> usize {
+        self.expansion
+    }
+
+    fn verify(&self, plaintext: &[u8]) -> Choice {
+        (self.verify)(plaintext)
+    }
+}
+
+const ZEROS: [u8; 64] = [0; 64];
+
+impl<Core: WideBlockCipherCore> WideBlockCipher<Core> {
+    /// Encipher the plaintext in the buffer, extended with the [`Redundancy::expansion`] zero bytes.
+    pub fn encrypt_in_place_redundant(
+        self,
+        tweak: &[u8],
+        buffer: &mut impl aead::Buffer,
+        redundancy: &impl Redundancy,
+    ) -> aead::Result<()> {
+        let n = buffer.len();
+        let mut expansion = redundancy.expansion();
+        while expansion > 0 {
+            let m = core::cmp::min(expansion, ZEROS.len());
+            if let Err(err) = buffer.extend_from_slice(&ZEROS[..m]) {
+                buffer.truncate(n);
+                return Err(err);
+            }
+            expansion -= m;
+        }
+        self.encrypt_inout(tweak, InOutBuf::from(buffer.as_mut()));
+        Ok(())
+    }
+
+    /// Decipher the ciphertext in the buffer and verify the redundancy of the plaintext.
+    ///
+    /// On failure the buffer is restored to the original ciphertext.
+    pub fn decrypt_in_place_redundant<'out>(
+        self,
+        tweak: &[u8],
+        buffer: &'out mut impl aead::Buffer,
+        redundancy: &impl Redundancy,
+    ) -> aead::Result<&'out mut [u8]> {
+        let expansion = redundancy.expansion();
+        if buffer.len() < expansion {
+            return Err(aead::Error);
+        }
+
+        let n = buffer.len() - expansion;
+
+        self.clone()
+            .decrypt_inout(tweak, InOutBuf::from(buffer.as_mut()));
+
+        if !bool::from(verify_redundant(buffer.as_ref(), n, redundancy)) {
+            self.encrypt_inout(tweak, InOutBuf::from(buffer.as_mut()));
+            return Err(aead::Error);
+        }
+
+        buffer.truncate(n);
+        Ok(buffer.as_mut())
+    }
+
+    /// Decipher `ciphertext` into `scratch`, verify the redundancy of the plaintext, and only
+    /// then copy the plaintext into `out`.
+    ///
+    /// `scratch` must be the same length as the ciphertext, and `out` must be the length of the
+    /// plaintext. Unverified plaintext is never written to `out`, and `scratch` is wiped before
+    /// returning.
+    pub fn decrypt_redundant_to(
+        self,
+        tweak: &[u8],
+        ciphertext: &[u8],
+        scratch: &mut [u8],
+        out: &mut [u8],
+        redundancy: &impl Redundancy,
+    ) -> aead::Result<()> {
+        let expansion = redundancy.expansion();
+        if ciphertext.len() < expansion || ciphertext.len() - expansion != out.len() {
+            return Err(aead::Error);
+        }
+
+        let n = out.len();
+
+        let buffer = InOutBuf::new(ciphertext, &mut *scratch).map_err(|_| aead::Error)?;
+        self.decrypt_inout(tweak, buffer);
+
+        let valid = verify_redundant(scratch, n, redundancy);
+        if bool::from(valid) {
+            out.copy_from_slice(&scratch[..n]);
+        }
+        core_api::wipe(scratch);
+
+        if bool::from(valid) {
+            Ok(())
+        } else {
+            Err(aead::Error)
+        }
+    }
+}
+
+/// Check that the deciphered `buffer` is `n` bytes of valid plaintext followed by zero bytes.
+fn verify_redundant(buffer: &[u8], n: usize, redundancy: &impl Redundancy) -> Choice {
+    let (plaintext, zeros) = buffer.split_at(n);
+    let zeros = zeros.iter().fold(0, |acc, b| acc | b).ct_eq(&0);
+    zeros & redundancy.verify(plaintext)
+}
+
 pub struct WideBlockCipherAuthenticated<Core: WideBlockCipherCore, T: ArraySize> {
     inner: WideBlockCipher<Core>,
     _tag: PhantomData<T>,
@@ -122,10 +330,8 @@ impl<Core: WideBlockCipherCore, T: ArraySize> WideBlockCipherAuthenticated<Core,
         tweak: &[u8],
         buffer: &mut impl aead::Buffer,
     ) -> aead::Result<()> {
-        buffer.extend_from_slice(&Array::<u8, T>::default())?;
         self.inner
-            .encrypt_inout(tweak, InOutBuf::from(buffer.as_mut()));
-        Ok(())
+            .encrypt_in_place_redundant(tweak, buffer, &ZeroExpansion(T::USIZE))
     }
 
     pub fn decrypt_in_place<'out>(
@@ -133,25 +339,82 @@ impl<Core: WideBlockCipherCore, T: ArraySize> WideBlockCipherAuthenticated<Core,
         tweak: &[u8],
         buffer: &'out mut impl aead::Buffer,
     ) -> aead::Result<&'out mut [u8]> {
-        if buffer.len() < T::USIZE {
-            return Err(aead::Error);
-        }
+        self.inner
+            .decrypt_in_place_redundant(tweak, buffer, &ZeroExpansion(T::USIZE))
+    }
+
+    /// Decrypt `ciphertext` into `out` without ever exposing unverified plaintext in `out`.
+    ///
+    /// See [`WideBlockCipher::decrypt_redundant_to`].
+    pub fn decrypt_to(
+        self,
+        tweak: &[u8],
+        ciphertext: &[u8],
+        scratch: &mut [u8],
+        out: &mut [u8],
+    ) -> aead::Result<()> {
+        self.inner
+            .decrypt_redundant_to(tweak, ciphertext, scratch, out, &ZeroExpansion(T::USIZE))
+    }
+}
+
+/// [`WideBlockCipherAuthenticated`] with the alignment and tag size chosen at runtime.
+pub struct RuntimeWideBlockCipherAuthenticated<Core: WideBlockCipherCore> {
+    inner: WideBlockCipher<Core>,
+    tag_size: usize,
+}
 
-        let n = buffer.len() - T::USIZE;
+impl<Core: WideBlockCipherCore> RuntimeWideBlockCipherAuthenticated<Core> {
+    /// The alignment must be valid for [`WideBlockCipher::with_alignment`], and the tag size
+    /// must be between [`MIN_TAG_SIZE`](params::MIN_TAG_SIZE) and
+    /// [`MAX_TAG_SIZE`](params::MAX_TAG_SIZE).
+    pub fn init(key: &[u8], alignment: usize, tag_size: usize) -> Result<Self, InvalidParameters> {
+        params::check_tag_size(tag_size)?;
+        Ok(Self {
+            inner: WideBlockCipher::with_alignment(key, alignment)?,
+            tag_size,
+        })
+    }
 
+    /// The number of zero bytes the plaintext is extended with.
+    pub fn tag_size(&self) -> usize {
+        self.tag_size
+    }
+
+    pub fn encrypt_in_place(
+        self,
+        tweak: &[u8],
+        buffer: &mut impl aead::Buffer,
+    ) -> aead::Result<()> {
         self.inner
-            .clone()
-            .decrypt_inout(tweak, InOutBuf::from(buffer.as_mut()));
+            .encrypt_in_place_redundant(tweak, buffer, &ZeroExpansion(self.tag_size))
+    }
 
-        let tag = Array::<u8, T>::default();
-        let actual_tag = &buffer.as_ref()[n..];
-        if tag.ct_ne(actual_tag).into() {
-            self.inner
-                .encrypt_inout(tweak, InOutBuf::from(buffer.as_mut()));
-            return Err(aead::Error);
-        }
+    pub fn decrypt_in_place<'out>(
+        self,
+        tweak: &[u8],
+        buffer: &'out mut impl aead::Buffer,
+    ) -> aead::Result<&'out mut [u8]> {
+        self.inner
+            .decrypt_in_place_redundant(tweak, buffer, &ZeroExpansion(self.tag_size))
+    }
 
-        buffer.truncate(n);
-        Ok(buffer.as_mut())
+    /// Decrypt `ciphertext` into `out` without ever exposing unverified plaintext in `out`.
+    ///
+    /// See [`WideBlockCipher::decrypt_redundant_to`].
+    pub fn decrypt_to(
+        self,
+        tweak: &[u8],
+        ciphertext: &[u8],
+        scratch: &mut [u8],
+        out: &mut [u8],
+    ) -> aead::Result<()> {
+        self.inner.decrypt_redundant_to(
+            tweak,
+            ciphertext,
+            scratch,
+            out,
+            &ZeroExpansion(self.tag_size),
+        )
     }
 }
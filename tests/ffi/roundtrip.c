@@ -0,0 +1,154 @@
+/*
+ * Round trips through the C bindings, run by `cargo xtask ffi`.
+ *
+ * Exits with 0 on success, or reports the line of the first failed check.
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "farfalle.h"
+
+#define CHECK(cond)          \
+    do {                     \
+        if (!(cond)) {       \
+            return __LINE__; \
+        }                    \
+    } while (0)
+
+static const uint8_t key[32] = {1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16};
+static const uint8_t iv[16] = {42};
+static const uint8_t ad[] = "header";
+static const uint8_t message[] = "hello from C, over several blocks of the Xoodoo state";
+
+static int check_deck(void) {
+    FarfalleDeck *deck = NULL;
+    uint8_t a[32], b[32];
+
+    CHECK(farfalle_deck_new(key, sizeof key, &deck) == FARFALLE_STATUS_OK);
+    CHECK(farfalle_deck_update(deck, message, sizeof message) == FARFALLE_STATUS_OK);
+    CHECK(farfalle_deck_squeeze(deck, a, sizeof a) == FARFALLE_STATUS_OK);
+    farfalle_deck_free(deck);
+
+    CHECK(farfalle_deck_new(key, sizeof key, &deck) == FARFALLE_STATUS_OK);
+    CHECK(farfalle_deck_update(deck, message, 10) == FARFALLE_STATUS_OK);
+    CHECK(farfalle_deck_update(deck, message + 10, sizeof message - 10) == FARFALLE_STATUS_OK);
+    CHECK(farfalle_deck_squeeze(deck, b, sizeof b) == FARFALLE_STATUS_OK);
+    CHECK(memcmp(a, b, sizeof a) == 0);
+
+    /* squeezing ends the string, so the output moves on */
+    CHECK(farfalle_deck_squeeze(deck, b, sizeof b) == FARFALLE_STATUS_OK);
+    CHECK(memcmp(a, b, sizeof a) != 0);
+    farfalle_deck_free(deck);
+    return 0;
+}
+
+static int check_sane(void) {
+    FarfalleSane *enc = NULL, *dec = NULL;
+    uint8_t buffer[sizeof message];
+    uint8_t tag[FARFALLE_SANE_TAG_LEN];
+
+    CHECK(farfalle_sane_new(key, sizeof key, iv, sizeof iv, &enc) == FARFALLE_STATUS_OK);
+    CHECK(farfalle_sane_new(key, sizeof key, iv, sizeof iv, &dec) == FARFALLE_STATUS_OK);
+
+    for (int i = 0; i < 3; i++) {
+        memcpy(buffer, message, sizeof message);
+        CHECK(farfalle_sane_encrypt(enc, ad, sizeof ad, buffer, sizeof buffer, tag) ==
+              FARFALLE_STATUS_OK);
+        CHECK(memcmp(buffer, message, sizeof message) != 0);
+
+        tag[0] ^= 1;
+        CHECK(farfalle_sane_decrypt(dec, ad, sizeof ad, buffer, sizeof buffer, tag) ==
+              FARFALLE_STATUS_AUTHENTICATION_FAILED);
+        tag[0] ^= 1;
+        CHECK(farfalle_sane_decrypt(dec, ad, sizeof ad, buffer, sizeof buffer, tag) ==
+              FARFALLE_STATUS_OK);
+        CHECK(memcmp(buffer, message, sizeof message) == 0);
+    }
+
+    farfalle_sane_free(enc);
+    farfalle_sane_free(dec);
+    return 0;
+}
+
+static int check_sanse(void) {
+    FarfalleSanse *enc = NULL, *dec = NULL;
+    uint8_t buffer[sizeof message];
+    uint8_t tag[FARFALLE_SANSE_TAG_LEN];
+
+    CHECK(farfalle_sanse_new(key, sizeof key, &enc) == FARFALLE_STATUS_OK);
+    CHECK(farfalle_sanse_new(key, sizeof key, &dec) == FARFALLE_STATUS_OK);
+
+    for (int i = 0; i < 3; i++) {
+        memcpy(buffer, message, sizeof message);
+        CHECK(farfalle_sanse_encrypt(enc, ad, sizeof ad, buffer, sizeof buffer, tag) ==
+              FARFALLE_STATUS_OK);
+
+        buffer[0] ^= 1;
+        CHECK(farfalle_sanse_decrypt(dec, ad, sizeof ad, buffer, sizeof buffer, tag) ==
+              FARFALLE_STATUS_AUTHENTICATION_FAILED);
+        buffer[0] ^= 1;
+        CHECK(farfalle_sanse_decrypt(dec, ad, sizeof ad, buffer, sizeof buffer, tag) ==
+              FARFALLE_STATUS_OK);
+        CHECK(memcmp(buffer, message, sizeof message) == 0);
+    }
+
+    farfalle_sanse_free(enc);
+    farfalle_sanse_free(dec);
+    return 0;
+}
+
+static int check_wbc(void) {
+    FarfalleWbc *wbc = NULL;
+    uint8_t buffer[sizeof message];
+
+    CHECK(farfalle_wbc_new(key, sizeof key, &wbc) == FARFALLE_STATUS_OK);
+    memcpy(buffer, message, sizeof message);
+    CHECK(farfalle_wbc_encrypt(wbc, ad, sizeof ad, buffer, sizeof buffer) == FARFALLE_STATUS_OK);
+    CHECK(memcmp(buffer, message, sizeof message) != 0);
+    CHECK(farfalle_wbc_decrypt(wbc, ad, sizeof ad, buffer, sizeof buffer) == FARFALLE_STATUS_OK);
+    CHECK(memcmp(buffer, message, sizeof message) == 0);
+    farfalle_wbc_free(wbc);
+    return 0;
+}
+
+static int check_errors(void) {
+    FarfalleSane *sane = NULL;
+    uint8_t long_key[FARFALLE_MAX_KEY_LEN + 1] = {0};
+    uint8_t buffer[4] = {0};
+
+    CHECK(farfalle_sane_new(long_key, sizeof long_key, iv, sizeof iv, &sane) ==
+          FARFALLE_STATUS_INVALID_LENGTH);
+    CHECK(sane == NULL);
+    CHECK(farfalle_sane_new(NULL, 16, iv, sizeof iv, &sane) == FARFALLE_STATUS_NULL_POINTER);
+    CHECK(farfalle_sane_new(key, sizeof key, iv, sizeof iv, NULL) == FARFALLE_STATUS_NULL_POINTER);
+    CHECK(farfalle_sane_encrypt(NULL, NULL, 0, buffer, sizeof buffer, buffer) ==
+          FARFALLE_STATUS_NULL_POINTER);
+
+    /* an empty key and IV are allowed */
+    CHECK(farfalle_sane_new(NULL, 0, NULL, 0, &sane) == FARFALLE_STATUS_OK);
+    CHECK(farfalle_sane_encrypt(sane, NULL, 0, buffer, sizeof buffer, NULL) ==
+          FARFALLE_STATUS_NULL_POINTER);
+    farfalle_sane_free(sane);
+    farfalle_sane_free(NULL);
+    return 0;
+}
+
+static int roundtrip(void) {
+    int line;
+    if ((line = check_deck()) != 0) return line;
+    if ((line = check_sane()) != 0) return line;
+    if ((line = check_sanse()) != 0) return line;
+    if ((line = check_wbc()) != 0) return line;
+    if ((line = check_errors()) != 0) return line;
+    return 0;
+}
+
+int main(void) {
+    int line = roundtrip();
+    if (line != 0) {
+        fprintf(stderr, "roundtrip.c:%d: check failed\n", line);
+        return 1;
+    }
+    return 0;
+}
@@ -0,0 +1,79 @@
+//! Development tasks, run with `cargo xtask <task>`.
+//!
+//! - `ffi`: check that `include/farfalle.h` is the cbindgen output for `src/ffi.rs`, build the
+//!   `farfalle-ffi` libraries and run the C round trips in `tests/ffi/roundtrip.c` against them.
+//!   Needs a C compiler, `cc` or the one named by `CC`, and a Unix-like linker.
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+type Result<T = ()> = core::result::Result<T, Box<dyn Error>>;
+
+fn main() -> ExitCode {
+    let result = match env::args().nth(1).as_deref() {
+        Some("ffi") => ffi(),
+        _ => Err("usage: cargo xtask ffi".into()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn ffi() -> Result {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    check_header(root)?;
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    run(Command::new(cargo)
+        .current_dir(root)
+        .args(["build", "--package", "farfalle-ffi"]))?;
+
+    let target_dir =
+        env::var_os("CARGO_TARGET_DIR").map_or_else(|| root.join("target"), PathBuf::from);
+    let lib_dir = target_dir.join("debug");
+    let exe = lib_dir.join("ffi-roundtrip");
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".into());
+    run(Command::new(cc)
+        .current_dir(root)
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-Iinclude"])
+        .arg("tests/ffi/roundtrip.c")
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .args(["-lfarfalle_ffi", "-o"])
+        .arg(&exe))?;
+    run(&mut Command::new(&exe))
+}
+
+/// Fails if the committed header differs from what cbindgen generates for `src/ffi.rs`.
+fn check_header(root: &Path) -> Result {
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml"))?;
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_crate(root)
+        .with_config(config)
+        .generate()?
+        .write(&mut generated);
+
+    if generated != fs::read(root.join("include/farfalle.h"))? {
+        return Err("include/farfalle.h is out of date, update it with \
+                    `cbindgen --config cbindgen.toml --output include/farfalle.h`"
+            .into());
+    }
+    Ok(())
+}
+
+fn run(command: &mut Command) -> Result {
+    let status = command.status()?;
+    if !status.success() {
+        return Err(format!("{command:?} failed with {status}").into());
+    }
+    Ok(())
+}